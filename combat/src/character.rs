@@ -0,0 +1,202 @@
+use std::fmt;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{Equipment, Timers};
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+pub struct Character {
+    pub hit_points: u64,
+    pub damage: u64,
+    pub armour: u64,
+    pub mana: u64,
+    // Turns left on each active effect, by whatever numbers the game gives its effects
+    pub effects: Timers<usize>,
+}
+
+// What a character fights with when there is no magic
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Stats {
+    pub hit_points: u64,
+    pub damage: u64,
+    pub armour: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Player,
+    Boss,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Player => write!(f, "player"),
+            Self::Boss => write!(f, "boss"),
+        }
+    }
+}
+
+// Damage done by an attack against the armour, which is never less than one
+pub fn damage(attack: u64, armour: u64) -> u64 {
+    attack.saturating_sub(armour).max(1)
+}
+
+// An attribute and its value from a line of a stat file, such as `Hit Points: 103`, or
+// None for a blank line
+fn attribute(update: &str) -> Result<Option<(&str, u64)>> {
+    let update = update.trim();
+    if update.is_empty() {
+        return Ok(None);
+    }
+    let (attr, value) = update
+        .split_once(':')
+        .context("should have an attribute name")?;
+    let value = value.trim().parse()?;
+    log::debug!("{attr} <- {value}");
+    Ok(Some((attr.trim(), value)))
+}
+
+impl Stats {
+    pub fn update(&mut self, update: &str) -> Result<()> {
+        match attribute(update)? {
+            Some((attr, value)) => self.set(attr, value),
+            None => Ok(()),
+        }
+    }
+
+    fn set(&mut self, attr: &str, value: u64) -> Result<()> {
+        match attr.to_lowercase().as_str() {
+            "hit points" => self.hit_points = value,
+            "damage" => self.damage = value,
+            "armor" => self.armour = value,
+            _ => return Err(anyhow!("unknown attribute {attr}")),
+        };
+        Ok(())
+    }
+
+    pub fn equip(&mut self, item: &impl Equipment) {
+        let bonus = item.bonus();
+        self.damage += bonus.damage;
+        self.armour += bonus.armour;
+    }
+
+    pub fn damage_to(&self, defender: &Stats) -> u64 {
+        damage(self.damage, defender.armour)
+    }
+
+    // Attacks needed to bring the defender down to no hit points
+    pub fn turns_to_kill(&self, defender: &Stats) -> u64 {
+        defender
+            .hit_points
+            .div_ceil(self.damage_to(defender))
+            .max(1)
+    }
+}
+
+impl Character {
+    // Set an attribute from a line of a stat file, such as `Hit Points: 103`
+    pub fn update(&mut self, update: &str) -> Result<()> {
+        let Some((attr, value)) = attribute(update)? else {
+            return Ok(());
+        };
+        if attr.eq_ignore_ascii_case("mana") {
+            self.mana = value;
+            return Ok(());
+        }
+        let mut stats = self.stats();
+        stats.set(attr, value)?;
+        self.set_stats(stats);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            hit_points: self.hit_points,
+            damage: self.damage,
+            armour: self.armour,
+        }
+    }
+
+    fn set_stats(&mut self, stats: Stats) {
+        (self.hit_points, self.damage, self.armour) =
+            (stats.hit_points, stats.damage, stats.armour);
+    }
+
+    pub fn equip(&mut self, item: &impl Equipment) {
+        let mut stats = self.stats();
+        stats.equip(item);
+        self.set_stats(stats);
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.hit_points > 0
+    }
+}
+
+// Characters in stat file form, separated by blank lines
+pub fn parse_characters(s: &str) -> Result<Vec<Character>> {
+    let mut characters = Vec::new();
+    let mut character = None;
+    for (number, line) in s.lines().enumerate() {
+        if line.trim().is_empty() {
+            characters.extend(character.take());
+        } else {
+            character
+                .get_or_insert_with(Character::default)
+                .update(line)
+                .with_context(|| format!("line {}", number + 1))?;
+        }
+    }
+    characters.extend(character);
+    Ok(characters)
+}
+
+pub fn load_characters(filename: &str) -> Result<Vec<Character>> {
+    parse_characters(&std::fs::read_to_string(filename)?).with_context(|| filename.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bonus;
+
+    #[test]
+    fn stats() -> Result<()> {
+        let characters =
+            parse_characters("Hit Points: 103\nDamage: 9\nArmor: 2\n\n\nHit Points: 12\n")?;
+        assert_eq!(2, characters.len());
+        let boss = &characters[0];
+        assert_eq!((103, 9, 2), (boss.hit_points, boss.damage, boss.armour));
+        assert_eq!(12, characters[1].hit_points);
+        let mut stats = characters[1].stats();
+        stats.update("Armor: 4")?;
+        assert_eq!((12, 4), (stats.hit_points, stats.armour));
+        assert!(stats.update("Mana: 4").is_err());
+        assert!(parse_characters("Speed: 4").is_err());
+        assert!(parse_characters("Hit Points").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn attacks() {
+        let mut player = Stats {
+            hit_points: 8,
+            ..Stats::default()
+        };
+        player.equip(&Bonus {
+            damage: 5,
+            armour: 5,
+        });
+        let boss = Stats {
+            hit_points: 12,
+            damage: 7,
+            armour: 2,
+        };
+        assert_eq!(3, player.damage_to(&boss));
+        assert_eq!(4, player.turns_to_kill(&boss));
+        assert_eq!(4, boss.turns_to_kill(&player));
+        // However strong the armour, every attack does some damage
+        assert_eq!(1, damage(7, 100));
+    }
+}
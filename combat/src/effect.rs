@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use crate::Character;
+
+// What happens when a spell is cast or on each turn its effect is active. Damage is done
+// to the target, while healing and mana go to the caster.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Effect {
+    pub damage: u64,
+    pub heal: u64,
+    pub armour: u64,
+    pub mana: u64,
+}
+
+impl Effect {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Armour is left to the caller, as it lasts only as long as the effect does
+    pub fn apply(&self, caster: &mut Character, target: &mut Character) {
+        target.hit_points = target.hit_points.saturating_sub(self.damage);
+        caster.hit_points += self.heal;
+        caster.mana += self.mana;
+    }
+}
+
+// Effects with the turns each has left
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Timers<K: Ord> {
+    timers: BTreeMap<K, u64>,
+}
+
+impl<K: Ord> Default for Timers<K> {
+    fn default() -> Self {
+        Self {
+            timers: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord + Copy> Timers<K> {
+    // Nothing happens for an effect lasting no turns
+    pub fn start(&mut self, key: K, turns: u64) {
+        if turns > 0 {
+            self.timers.insert(key, turns);
+        }
+    }
+
+    pub fn is_active(&self, key: K) -> bool {
+        self.timers.contains_key(&key)
+    }
+
+    pub fn remaining(&self, key: K) -> Option<u64> {
+        self.timers.get(&key).copied()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.timers.keys().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, u64)> + '_ {
+        self.timers.iter().map(|(&key, &turns)| (key, turns))
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    // Count every timer down a turn, returning each with the turns it has left. Those
+    // that reach zero are dropped.
+    pub fn tick(&mut self) -> Vec<(K, u64)> {
+        let ticked = self
+            .timers
+            .iter_mut()
+            .map(|(&key, turns)| {
+                *turns -= 1;
+                (key, *turns)
+            })
+            .collect();
+        self.timers.retain(|_, turns| *turns > 0);
+        ticked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers() {
+        let mut timers = Timers::default();
+        timers.start(2, 2);
+        timers.start(0, 1);
+        timers.start(1, 0);
+        assert_eq!(vec![(0, 1), (2, 2)], timers.iter().collect::<Vec<_>>());
+        assert_eq!(vec![(0, 0), (2, 1)], timers.tick());
+        assert!(!timers.is_active(0) && timers.is_active(2));
+        assert_eq!(vec![(2, 0)], timers.tick());
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn apply() {
+        let mut player = Character::default();
+        let mut boss = Character {
+            hit_points: 2,
+            ..Character::default()
+        };
+        let drain = Effect {
+            damage: 3,
+            heal: 2,
+            armour: 7,
+            mana: 101,
+        };
+        drain.apply(&mut player, &mut boss);
+        assert_eq!((2, 0, 101), (player.hit_points, player.armour, player.mana));
+        assert_eq!(0, boss.hit_points);
+        assert!(!drain.is_empty() && Effect::default().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+use std::{iter::Sum, ops::Add};
+
+// What an item adds to the character who has it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Bonus {
+    pub damage: u64,
+    pub armour: u64,
+}
+
+pub trait Equipment {
+    fn bonus(&self) -> Bonus;
+}
+
+impl Equipment for Bonus {
+    fn bonus(&self) -> Bonus {
+        *self
+    }
+}
+
+impl Add for Bonus {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            damage: self.damage + other.damage,
+            armour: self.armour + other.armour,
+        }
+    }
+}
+
+impl Sum for Bonus {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
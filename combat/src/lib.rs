@@ -0,0 +1,11 @@
+// The rules shared by the role playing games of days 21 and 22: characters who attack
+// each other, effects that last for a number of turns and equipment that adds to a
+// character's damage and armour.
+
+pub use character::{damage, load_characters, parse_characters, Character, Side, Stats};
+pub use effect::{Effect, Timers};
+pub use equipment::{Bonus, Equipment};
+
+mod character;
+mod effect;
+mod equipment;
@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+// Marker used in FOLLOW sets for the end of the input.
+pub const END_OF_INPUT: &str = "$";
+
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub start_symbol: String,
+    pub nonterminals: BTreeSet<String>,
+    pub terminals: BTreeSet<String>,
+    pub nullable: BTreeSet<String>,
+    pub first: BTreeMap<String, BTreeSet<String>>,
+    pub follow: BTreeMap<String, BTreeSet<String>>,
+    pub unproductive: BTreeSet<String>,
+    pub unreachable: BTreeSet<String>,
+    pub left_recursive: BTreeSet<String>,
+}
+
+impl Analysis {
+    // Any symbol which is the source of a rule is a nonterminal, everything else is a terminal.
+    pub fn new(start_symbol: &str, productions: &[(String, Vec<String>)]) -> Self {
+        let nonterminals = productions
+            .iter()
+            .map(|(source, _)| source.to_owned())
+            .collect::<BTreeSet<_>>();
+        let terminals = productions
+            .iter()
+            .flat_map(|(_, target)| target.iter())
+            .filter(|symbol| !nonterminals.contains(*symbol))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        let mut analysis = Self {
+            start_symbol: start_symbol.to_owned(),
+            nonterminals,
+            terminals,
+            ..Self::default()
+        };
+        analysis.nullable = analysis.find_nullable(productions);
+        analysis.first = analysis.find_first(productions);
+        analysis.follow = analysis.find_follow(productions);
+        analysis.unproductive = analysis.find_unproductive(productions);
+        analysis.unreachable = analysis.find_unreachable(productions);
+        analysis.left_recursive = analysis.find_left_recursive(productions);
+        analysis
+    }
+
+    pub fn is_terminal(&self, symbol: &str) -> bool {
+        !self.nonterminals.contains(symbol)
+    }
+
+    // FIRST set of a sequence of symbols, and whether the whole sequence is nullable.
+    pub fn first_of(&self, symbols: &[String]) -> (BTreeSet<String>, bool) {
+        let mut first = BTreeSet::new();
+        for symbol in symbols {
+            if self.is_terminal(symbol) {
+                first.insert(symbol.to_owned());
+                return (first, false);
+            }
+            if let Some(f) = self.first.get(symbol) {
+                first.extend(f.iter().cloned());
+            }
+            if !self.nullable.contains(symbol) {
+                return (first, false);
+            }
+        }
+        (first, true)
+    }
+
+    pub fn diagnostics(&self) -> Vec<String> {
+        let mut diagnostics = Vec::new();
+        if !self.nonterminals.contains(&self.start_symbol) {
+            diagnostics.push(format!("start symbol {} has no rules", self.start_symbol));
+        }
+        for symbol in &self.unproductive {
+            diagnostics.push(format!("{symbol} cannot derive any string of terminals"));
+        }
+        for symbol in &self.unreachable {
+            diagnostics.push(format!(
+                "{symbol} is not reachable from {}",
+                self.start_symbol
+            ));
+        }
+        for symbol in &self.left_recursive {
+            diagnostics.push(format!("{symbol} is left recursive"));
+        }
+        diagnostics
+    }
+
+    fn find_nullable(&self, productions: &[(String, Vec<String>)]) -> BTreeSet<String> {
+        let mut nullable = BTreeSet::new();
+        loop {
+            let mut changed = false;
+            for (source, target) in productions {
+                if !nullable.contains(source) && target.iter().all(|s| nullable.contains(s)) {
+                    nullable.insert(source.to_owned());
+                    changed = true;
+                }
+            }
+            if !changed {
+                break nullable;
+            }
+        }
+    }
+
+    fn find_first(
+        &self,
+        productions: &[(String, Vec<String>)],
+    ) -> BTreeMap<String, BTreeSet<String>> {
+        let mut first: BTreeMap<String, BTreeSet<String>> = self
+            .nonterminals
+            .iter()
+            .map(|symbol| (symbol.to_owned(), BTreeSet::new()))
+            .collect();
+        loop {
+            let mut changed = false;
+            for (source, target) in productions {
+                let mut additions = BTreeSet::new();
+                for symbol in target {
+                    if self.is_terminal(symbol) {
+                        additions.insert(symbol.to_owned());
+                        break;
+                    }
+                    additions.extend(first[symbol].iter().cloned());
+                    if !self.nullable.contains(symbol) {
+                        break;
+                    }
+                }
+                let entry = first.get_mut(source).unwrap();
+                let before = entry.len();
+                entry.extend(additions);
+                changed |= entry.len() != before;
+            }
+            if !changed {
+                break first;
+            }
+        }
+    }
+
+    fn find_follow(
+        &self,
+        productions: &[(String, Vec<String>)],
+    ) -> BTreeMap<String, BTreeSet<String>> {
+        let mut follow: BTreeMap<String, BTreeSet<String>> = self
+            .nonterminals
+            .iter()
+            .map(|symbol| (symbol.to_owned(), BTreeSet::new()))
+            .collect();
+        if let Some(f) = follow.get_mut(&self.start_symbol) {
+            f.insert(END_OF_INPUT.to_owned());
+        }
+        loop {
+            let mut changed = false;
+            for (source, target) in productions {
+                for (idx, symbol) in target.iter().enumerate() {
+                    if self.is_terminal(symbol) {
+                        continue;
+                    }
+                    let (mut additions, rest_nullable) = self.first_of(&target[idx + 1..]);
+                    if rest_nullable {
+                        additions.extend(follow[source].iter().cloned());
+                    }
+                    let entry = follow.get_mut(symbol).unwrap();
+                    let before = entry.len();
+                    entry.extend(additions);
+                    changed |= entry.len() != before;
+                }
+            }
+            if !changed {
+                break follow;
+            }
+        }
+    }
+
+    fn find_unproductive(&self, productions: &[(String, Vec<String>)]) -> BTreeSet<String> {
+        let mut productive = BTreeSet::new();
+        loop {
+            let mut changed = false;
+            for (source, target) in productions {
+                if !productive.contains(source)
+                    && target
+                        .iter()
+                        .all(|s| self.is_terminal(s) || productive.contains(s))
+                {
+                    productive.insert(source.to_owned());
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        self.nonterminals.difference(&productive).cloned().collect()
+    }
+
+    fn find_unreachable(&self, productions: &[(String, Vec<String>)]) -> BTreeSet<String> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = VecDeque::from([self.start_symbol.to_owned()]);
+        while let Some(symbol) = pending.pop_front() {
+            if !reachable.insert(symbol.to_owned()) {
+                continue;
+            }
+            for (_, target) in productions.iter().filter(|(source, _)| *source == symbol) {
+                pending.extend(target.iter().cloned());
+            }
+        }
+        self.nonterminals
+            .iter()
+            .chain(self.terminals.iter())
+            .filter(|symbol| !reachable.contains(*symbol))
+            .cloned()
+            .collect()
+    }
+
+    fn find_left_recursive(&self, productions: &[(String, Vec<String>)]) -> BTreeSet<String> {
+        // A -> B when B can appear leftmost in a derivation step from A
+        let mut leftmost: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (source, target) in productions {
+            for symbol in target {
+                if self.is_terminal(symbol) {
+                    break;
+                }
+                leftmost
+                    .entry(source.as_str())
+                    .or_default()
+                    .insert(symbol.as_str());
+                if !self.nullable.contains(symbol) {
+                    break;
+                }
+            }
+        }
+
+        let mut left_recursive = BTreeSet::new();
+        for symbol in &self.nonterminals {
+            let mut seen = BTreeSet::new();
+            let mut pending = leftmost
+                .get(symbol.as_str())
+                .map(|s| s.iter().copied().collect::<Vec<_>>())
+                .unwrap_or_default();
+            while let Some(next) = pending.pop() {
+                if next == symbol {
+                    left_recursive.insert(symbol.to_owned());
+                    break;
+                }
+                if seen.insert(next) {
+                    if let Some(s) = leftmost.get(next) {
+                        pending.extend(s.iter().copied());
+                    }
+                }
+            }
+        }
+        left_recursive
+    }
+}
+
+fn write_set(f: &mut fmt::Formatter<'_>, name: &str, set: &BTreeSet<String>) -> fmt::Result {
+    if set.is_empty() {
+        writeln!(f, "{name}: -")
+    } else {
+        let symbols = set.iter().cloned().collect::<Vec<_>>().join(" ");
+        writeln!(f, "{name} ({}): {symbols}", set.len())
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "start symbol: {}", self.start_symbol)?;
+        write_set(f, "nonterminals", &self.nonterminals)?;
+        write_set(f, "terminals", &self.terminals)?;
+        write_set(f, "nullable", &self.nullable)?;
+        write_set(f, "unproductive", &self.unproductive)?;
+        write_set(f, "unreachable", &self.unreachable)?;
+        write_set(f, "left recursive", &self.left_recursive)?;
+        writeln!(f, "FIRST:")?;
+        for (symbol, set) in &self.first {
+            write_set(f, &format!("  {symbol}"), set)?;
+        }
+        writeln!(f, "FOLLOW:")?;
+        for (symbol, set) in &self.follow {
+            write_set(f, &format!("  {symbol}"), set)?;
+        }
+        let diagnostics = self.diagnostics();
+        if diagnostics.is_empty() {
+            writeln!(f, "no problems found")
+        } else {
+            writeln!(f, "problems ({}):", diagnostics.len())?;
+            for diagnostic in diagnostics {
+                writeln!(f, "  {diagnostic}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{Grammar, SimpleRule};
+
+    fn grammar(rules: &[(&str, &str)]) -> Grammar<SimpleRule> {
+        let mut grammar = Grammar::new();
+        for (source, target) in rules {
+            grammar.add_rule(&SimpleRule {
+                source: source.to_string(),
+                target: target.to_string(),
+            });
+        }
+        grammar
+    }
+
+    fn words(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_owned()).collect()
+    }
+
+    fn set(s: &str) -> BTreeSet<String> {
+        words(s).into_iter().collect()
+    }
+
+    #[test]
+    fn expression_grammar() {
+        let grammar = grammar(&[
+            ("E", "T X"),
+            ("X", "+ T X"),
+            ("X", ""),
+            ("T", "F Y"),
+            ("Y", "* F Y"),
+            ("Y", ""),
+            ("F", "( E )"),
+            ("F", "id"),
+        ]);
+        let analysis = grammar.analyse("E", words);
+        assert_eq!(set("X Y"), analysis.nullable);
+        assert_eq!(set("( id"), analysis.first["E"]);
+        assert_eq!(set("+"), analysis.first["X"]);
+        assert_eq!(set(") $"), analysis.follow["E"]);
+        assert_eq!(set(") $"), analysis.follow["X"]);
+        assert_eq!(set("+ ) $"), analysis.follow["T"]);
+        assert_eq!(set("* + ) $"), analysis.follow["F"]);
+        assert!(analysis.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn useless_symbols() {
+        let grammar = grammar(&[
+            ("S", "A"),
+            ("S", "S a"),
+            ("S", "b"),
+            ("A", "A c"),
+            ("B", "d"),
+        ]);
+        let analysis = grammar.analyse("S", words);
+        assert_eq!(set("A"), analysis.unproductive);
+        assert_eq!(set("B d"), analysis.unreachable);
+        assert_eq!(set("A S"), analysis.left_recursive);
+        assert_eq!(5, analysis.diagnostics().len());
+    }
+}
@@ -0,0 +1,582 @@
+use std::{collections::HashSet, fmt::Debug, fs, iter::Peekable, str::Chars};
+
+use anyhow::{bail, Context, Result};
+
+use super::{Grammar, Rule, SimpleRule};
+
+// Grammars in BNF / EBNF syntax:
+//
+//   # comment
+//   expr   ::= term { "+" term } ;
+//   term   ::= factor ( "*" factor )* ;
+//   factor ::= "(" expr ")" | <number> ;
+//   number ::= digit [ number ] ;
+//   digit  ::= '0' | '1' ;
+//
+// Nonterminals are bare names or <names> (spaces become _), terminals are quoted, and ""
+// is the empty string.
+// [x] and x? are optional, {x} and x* repeat zero or more times, x+ one or more.
+// The first rule defines the start symbol. Rule targets are stored as whitespace
+// separated symbols, with terminals keeping their (double) quotes; see `symbols`.
+
+pub fn load(filename: &str) -> Result<Grammar<SimpleRule>> {
+    let text = fs::read_to_string(filename).with_context(|| format!("reading {filename}"))?;
+    parse(&text).with_context(|| format!("parsing {filename}"))
+}
+
+pub fn parse(text: &str) -> Result<Grammar<SimpleRule>> {
+    let mut parser = Parser {
+        tokens: tokenise(text)?,
+        pos: 0,
+    };
+    let mut definitions = Vec::new();
+    while parser.peek().is_some() {
+        let line = parser.line();
+        let (name, expr) = parser.rule()?;
+        definitions.push((line, name, expr));
+    }
+
+    let mut names = definitions
+        .iter()
+        .map(|(_, name, _)| name.to_owned())
+        .collect::<HashSet<_>>();
+    for (line, name, expr) in &definitions {
+        if let Some(undefined) = expr.names().find(|symbol| !names.contains(*symbol)) {
+            bail!("line {line}: {undefined} is used by {name} but never defined");
+        }
+    }
+    let mut rules = Vec::new();
+    for (_, name, expr) in &definitions {
+        let mut desugar = Desugar {
+            name,
+            names: &mut names,
+            rules: Vec::new(),
+        };
+        for alternative in desugar.expand(expr) {
+            rules.push((name.to_owned(), alternative));
+        }
+        rules.append(&mut desugar.rules);
+    }
+
+    let mut grammar = Grammar::new();
+    for (source, target) in rules {
+        grammar.add_rule(&SimpleRule {
+            source,
+            target: target.join(" "),
+        });
+    }
+    Ok(grammar)
+}
+
+// Split a rule target into symbols, keeping quoted terminals (which may contain spaces) whole
+pub fn symbols(target: &str) -> Vec<String> {
+    let mut symbols = Vec::new();
+    let mut chars = target.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut symbol = String::from('"');
+            quoted(&mut chars, c, &mut symbol);
+            symbol.push('"');
+            symbols.push(symbol);
+        } else {
+            let mut symbol = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                symbol.push(c);
+                chars.next();
+            }
+            symbols.push(symbol);
+        }
+    }
+    symbols
+}
+
+// Read up to the closing quote, returning false if there is none
+fn quoted(chars: &mut Peekable<Chars<'_>>, quote: char, into: &mut String) -> bool {
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                into.push('\\');
+                if let Some(escaped) = chars.next() {
+                    into.push(escaped);
+                }
+            }
+            '"' if quote == '"' => return true,
+            '"' => into.push_str("\\\""),
+            _ if c == quote => return true,
+            _ => into.push(c),
+        }
+    }
+    false
+}
+
+impl<T: Debug + Clone + Rule> Grammar<T> {
+    // By convention the first rule defines the start symbol.
+    pub fn start_symbol(&self) -> Option<&str> {
+        self.rules.first().map(|rule| rule.source())
+    }
+
+    pub fn to_bnf<F>(&self, splitter: F) -> String
+    where
+        F: Fn(&str) -> Vec<String>,
+    {
+        let productions = self.productions(splitter);
+        let mut sources: Vec<&str> = Vec::new();
+        for (source, _) in &productions {
+            if !sources.contains(&source.as_str()) {
+                sources.push(source);
+            }
+        }
+
+        let symbol = |symbol: &str| {
+            if sources.contains(&symbol) {
+                if is_name(symbol) {
+                    symbol.to_owned()
+                } else {
+                    format!("<{symbol}>")
+                }
+            } else if is_quoted(symbol) {
+                symbol.to_owned()
+            } else {
+                format!("\"{}\"", symbol.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+        };
+        let mut bnf = String::new();
+        for source in &sources {
+            let alternatives = productions
+                .iter()
+                .filter(|(s, _)| s == source)
+                .map(|(_, target)| {
+                    if target.is_empty() {
+                        "\"\"".to_owned()
+                    } else {
+                        target
+                            .iter()
+                            .map(|s| symbol(s))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    }
+                })
+                .collect::<Vec<_>>();
+            bnf.push_str(&format!(
+                "{} ::= {} ;\n",
+                symbol(source),
+                alternatives.join(" | ")
+            ));
+        }
+        bnf
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_quoted(s: &str) -> bool {
+    s.len() >= 2
+        && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\'')))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Terminal(String),
+    Define,
+    Bar,
+    End,
+    Open(char),
+    Close(char),
+    Repeat(char),
+}
+
+fn tokenise(text: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_number = line_idx + 1;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                _ if c.is_whitespace() => continue,
+                '#' => break,
+                '|' => Token::Bar,
+                ';' => Token::End,
+                '(' | '[' | '{' => Token::Open(c),
+                ')' | ']' | '}' => Token::Close(c),
+                '?' | '*' | '+' => Token::Repeat(c),
+                '=' => Token::Define,
+                ':' => {
+                    if chars.next() != Some(':') || chars.next() != Some('=') {
+                        bail!("line {line_number}: expected ::=");
+                    }
+                    Token::Define
+                }
+                '"' | '\'' => {
+                    let mut terminal = String::new();
+                    if !quoted(&mut chars, c, &mut terminal) {
+                        bail!("line {line_number}: unterminated terminal {c}{terminal}");
+                    }
+                    Token::Terminal(terminal)
+                }
+                '<' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('>') => break,
+                            Some(c) if c.is_whitespace() => name.push('_'),
+                            Some(c) => name.push(c),
+                            None => bail!("line {line_number}: unterminated name <{name}"),
+                        }
+                    }
+                    if name.is_empty() {
+                        bail!("line {line_number}: empty <> name");
+                    }
+                    Token::Name(name)
+                }
+                _ if is_name(&c.to_string()) => {
+                    let mut name = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if !is_name(&c.to_string()) {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    Token::Name(name)
+                }
+                _ => bail!("line {line_number}: unexpected character {c:?}"),
+            };
+            tokens.push((line_number, token));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Symbol(String),
+    Sequence(Vec<Expr>),
+    Choice(Vec<Expr>),
+    Optional(Box<Expr>),
+    OneOrMore(Box<Expr>),
+}
+
+impl Expr {
+    // Nonterminals the expression refers to
+    fn names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match self {
+            Self::Symbol(symbol) if symbol.starts_with('"') => Box::new(std::iter::empty()),
+            Self::Symbol(symbol) => Box::new(std::iter::once(symbol.as_str())),
+            Self::Sequence(exprs) | Self::Choice(exprs) => {
+                Box::new(exprs.iter().flat_map(|expr| expr.names()))
+            }
+            Self::Optional(expr) | Self::OneOrMore(expr) => expr.names(),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos.min(self.tokens.len().saturating_sub(1)))
+            .map(|(line, _)| *line)
+            .unwrap_or_default()
+    }
+
+    fn rule(&mut self) -> Result<(String, Expr)> {
+        let line = self.line();
+        let name = match self.next() {
+            Some(Token::Name(name)) => name,
+            token => bail!("line {line}: expected a rule name, found {token:?}"),
+        };
+        if self.next() != Some(Token::Define) {
+            bail!("line {line}: expected ::= after {name}");
+        }
+        let expr = self.choice()?;
+        if self.peek() == Some(&Token::End) {
+            self.next();
+        }
+        Ok((name, expr))
+    }
+
+    fn choice(&mut self) -> Result<Expr> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some(&Token::Bar) {
+            self.next();
+            alternatives.push(self.sequence()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Expr::Choice(alternatives)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Expr> {
+        let mut items = Vec::new();
+        loop {
+            // A name followed by ::= starts the next rule
+            let next_rule = matches!(self.peek(), Some(Token::Name(_)))
+                && matches!(self.tokens.get(self.pos + 1), Some((_, Token::Define)));
+            if next_rule {
+                break;
+            }
+            let mut item = match self.peek() {
+                Some(Token::Name(_)) | Some(Token::Terminal(_)) | Some(Token::Open(_)) => {
+                    self.primary()?
+                }
+                _ => break,
+            };
+            while let Some(Token::Repeat(op)) = self.peek() {
+                item = match op {
+                    '?' => Expr::Optional(Box::new(item)),
+                    '+' => Expr::OneOrMore(Box::new(item)),
+                    _ => Expr::Optional(Box::new(Expr::OneOrMore(Box::new(item)))),
+                };
+                self.next();
+            }
+            items.push(item);
+        }
+        Ok(Expr::Sequence(items))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Name(name)) => Ok(Expr::Symbol(name)),
+            Some(Token::Terminal(terminal)) if terminal.is_empty() => {
+                Ok(Expr::Sequence(Vec::new()))
+            }
+            Some(Token::Terminal(terminal)) => Ok(Expr::Symbol(format!("\"{terminal}\""))),
+            Some(Token::Open(open)) => {
+                let expr = self.choice()?;
+                let close = match open {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                if self.next() != Some(Token::Close(close)) {
+                    bail!("line {line}: expected {close} to match {open}");
+                }
+                Ok(match open {
+                    '(' => expr,
+                    '[' => Expr::Optional(Box::new(expr)),
+                    _ => Expr::Optional(Box::new(Expr::OneOrMore(Box::new(expr)))),
+                })
+            }
+            token => bail!("line {line}: unexpected {token:?}"),
+        }
+    }
+}
+
+// Rewrites EBNF operators as plain alternatives, adding a new rule for each repetition and
+// for each optional part of a longer sequence
+struct Desugar<'a> {
+    name: &'a str,
+    names: &'a mut HashSet<String>,
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl Desugar<'_> {
+    fn expand(&mut self, expr: &Expr) -> Vec<Vec<String>> {
+        match expr {
+            Expr::Symbol(symbol) => vec![vec![symbol.to_owned()]],
+            Expr::Sequence(items) => {
+                let mut alternatives = vec![Vec::new()];
+                for item in items {
+                    // Expanding optionals in place would double the alternatives for each
+                    let expanded = match item {
+                        Expr::Optional(_) if items.len() > 1 => {
+                            let option = self.fresh("option");
+                            for alternative in self.expand(item) {
+                                self.rules.push((option.to_owned(), alternative));
+                            }
+                            vec![vec![option]]
+                        }
+                        _ => self.expand(item),
+                    };
+                    alternatives = alternatives
+                        .iter()
+                        .flat_map(|prefix| {
+                            expanded.iter().map(move |suffix| {
+                                prefix.iter().chain(suffix.iter()).cloned().collect()
+                            })
+                        })
+                        .collect();
+                }
+                alternatives
+            }
+            Expr::Choice(choices) => choices.iter().flat_map(|c| self.expand(c)).collect(),
+            Expr::Optional(expr) => {
+                let mut alternatives = self.expand(expr);
+                if !alternatives.iter().any(|a| a.is_empty()) {
+                    alternatives.push(Vec::new());
+                }
+                alternatives
+            }
+            Expr::OneOrMore(expr) => {
+                let repeat = self.fresh("repeat");
+                for alternative in self.expand(expr) {
+                    let mut recursive = alternative.clone();
+                    recursive.push(repeat.to_owned());
+                    self.rules.push((repeat.to_owned(), alternative));
+                    self.rules.push((repeat.to_owned(), recursive));
+                }
+                vec![vec![repeat]]
+            }
+        }
+    }
+
+    // A new nonterminal named after the rule
+    fn fresh(&mut self, kind: &str) -> String {
+        let mut name = format!("{}_{kind}", self.name);
+        let mut n = 1;
+        while self.names.contains(&name) {
+            n += 1;
+            name = format!("{}_{kind}{n}", self.name);
+        }
+        self.names.insert(name.to_owned());
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(grammar: &Grammar<SimpleRule>) -> Vec<(String, String)> {
+        grammar
+            .rules
+            .iter()
+            .map(|rule| (rule.source.to_owned(), rule.target.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn plain_bnf() -> Result<()> {
+        let grammar = parse(
+            r#"
+            # balanced brackets
+            <S> ::= "(" <S> ")" S | '' ;
+            "#,
+        )?;
+        assert_eq!(Some("S"), grammar.start_symbol());
+        assert_eq!(
+            vec![
+                ("S".to_owned(), r#""(" S ")" S"#.to_owned()),
+                ("S".to_owned(), "".to_owned())
+            ],
+            rules(&grammar)
+        );
+        let cnf_grammar = grammar.convert_to_cnf("S", symbols);
+        for (sentence, balanced) in [("", true), ("()", true), ("(())()", true), ("())", false)] {
+            let words = sentence
+                .chars()
+                .map(|c| format!("\"{c}\""))
+                .collect::<Vec<_>>();
+            assert_eq!(balanced, cnf_grammar.recognise("S", &words), "{sentence}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ebnf_operators() -> Result<()> {
+        let grammar = parse(
+            r#"
+            list = "[" [ item { "," item } ] "]"
+            item = "x"+ | ( "y" | "z" ) "!"?
+            "#,
+        )?;
+        let rules = rules(&grammar);
+        for (source, target) in [
+            ("list", r#""[" list_option "]""#),
+            ("list_option", "item list_option2"),
+            ("list_option", ""),
+            ("list_option2", "list_repeat"),
+            ("list_option2", ""),
+            ("list_repeat", r#""," item"#),
+            ("list_repeat", r#""," item list_repeat"#),
+            ("item", "item_repeat"),
+            ("item_repeat", r#""x" item_repeat"#),
+            ("item", r#""y" item_option"#),
+            ("item", r#""z" item_option"#),
+            ("item_option", r#""!""#),
+        ] {
+            assert!(
+                rules.contains(&(source.to_owned(), target.to_owned())),
+                "missing {source} ::= {target} in {rules:?}"
+            );
+        }
+        assert_eq!(14, rules.len());
+
+        // Each optional gets a rule of its own rather than doubling the alternatives
+        let grammar = parse(&format!("s ::= {} ;", "[ \"x\" ] ".repeat(20)))?;
+        assert_eq!(1 + 20 * 2, grammar.rules.len());
+        let cnf_grammar = grammar.convert_to_cnf("s", symbols);
+        assert!(cnf_grammar.recognise("s", &symbols(&"\"x\" ".repeat(7))));
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let grammar = parse(
+            r#"
+            sentence ::= <noun phrase> verb [ "very"* "quickly" ] ;
+            <noun phrase> ::= "the" ( "cat" | "dog" | "big \"red\" dog" ) ;
+            verb ::= 'runs' | "sleeps" ;
+            "#,
+        )?;
+        let bnf = grammar.to_bnf(symbols);
+        assert!(bnf.starts_with("sentence ::= noun_phrase verb"));
+        assert!(bnf.contains(r#""big \"red\" dog""#));
+        assert_eq!(rules(&grammar), rules(&parse(&bnf)?));
+        Ok(())
+    }
+
+    #[test]
+    fn unquoted_terminals() -> Result<()> {
+        let mut grammar = Grammar::new();
+        grammar.add_rule(&SimpleRule {
+            source: "e".to_owned(),
+            target: "H Rn F".to_owned(),
+        });
+        grammar.add_rule(&SimpleRule {
+            source: "H".to_owned(),
+            target: "".to_owned(),
+        });
+        let bnf = grammar.to_bnf(|s| s.split_whitespace().map(str::to_owned).collect());
+        assert_eq!("e ::= H \"Rn\" \"F\" ;\nH ::= \"\" ;\n", bnf);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("a ::= \"b").is_err());
+        assert!(parse("a ::= ( b").is_err());
+        assert!(parse("::= b").is_err());
+        assert!(parse("a b").is_err());
+        assert!(parse("a ::= <b").is_err());
+        assert!(parse("a ::= \"x\" b ;").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use super::CNFGrammar;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseTree {
+    // The start symbol deriving no words at all
+    Empty {
+        symbol: String,
+    },
+    Terminal {
+        symbol: String,
+        word: String,
+    },
+    Branch {
+        symbol: String,
+        left: Box<ParseTree>,
+        right: Box<ParseTree>,
+    },
+}
+
+impl ParseTree {
+    pub fn symbol(&self) -> &str {
+        match self {
+            Self::Empty { symbol } => symbol,
+            Self::Terminal { symbol, .. } => symbol,
+            Self::Branch { symbol, .. } => symbol,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parse {
+    pub cost: f64,
+    pub tree: ParseTree,
+}
+
+impl Parse {
+    pub fn probability(&self) -> f64 {
+        (-self.cost).exp()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Back {
+    Terminal(usize),
+    Split(usize, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    cost: f64,
+    back: Back,
+}
+
+impl CNFGrammar {
+    pub fn recognise(&self, start_symbol: &str, words: &[String]) -> bool {
+        self.parse(start_symbol, words).is_some()
+    }
+
+    // Viterbi style CYK: each chart cell keeps the cheapest derivation of each nonterminal.
+    pub fn parse(&self, start_symbol: &str, words: &[String]) -> Option<Parse> {
+        let n = words.len();
+        if n == 0 {
+            return self
+                .rules
+                .iter()
+                .filter(|rule| rule.source == start_symbol && rule.target.is_empty())
+                .map(|rule| Parse {
+                    cost: rule.cost,
+                    tree: ParseTree::Empty {
+                        symbol: rule.source.to_owned(),
+                    },
+                })
+                .min_by(|a, b| a.cost.total_cmp(&b.cost));
+        }
+
+        let mut nonterminals = HashMap::new();
+        for rule in &self.rules {
+            let idx = nonterminals.len();
+            nonterminals.entry(rule.source.as_str()).or_insert(idx);
+        }
+        let start = *nonterminals.get(start_symbol)?;
+
+        let mut by_left: HashMap<usize, Vec<(usize, usize, usize)>> = HashMap::new();
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if let [left, right] = &rule.target[..] {
+                if let (Some(&l), Some(&r)) = (
+                    nonterminals.get(left.as_str()),
+                    nonterminals.get(right.as_str()),
+                ) {
+                    by_left.entry(l).or_default().push((
+                        idx,
+                        nonterminals[rule.source.as_str()],
+                        r,
+                    ));
+                }
+            }
+        }
+
+        // chart[len - 1][start] holds the derivations of words[start..start + len]
+        let mut chart: Vec<Vec<HashMap<usize, Entry>>> = Vec::with_capacity(n);
+        chart.push(
+            words
+                .iter()
+                .map(|word| {
+                    let mut cell = HashMap::new();
+                    for (idx, rule) in self.rules.iter().enumerate() {
+                        if rule.target.len() == 1 && &rule.target[0] == word {
+                            Self::relax(
+                                &mut cell,
+                                nonterminals[rule.source.as_str()],
+                                rule.cost,
+                                Back::Terminal(idx),
+                            );
+                        }
+                    }
+                    cell
+                })
+                .collect(),
+        );
+        for len in 2..=n {
+            let mut row = Vec::with_capacity(n + 1 - len);
+            for begin in 0..=n - len {
+                let mut cell = HashMap::new();
+                for split in 1..len {
+                    let left_cell = &chart[split - 1][begin];
+                    let right_cell = &chart[len - split - 1][begin + split];
+                    if left_cell.is_empty() || right_cell.is_empty() {
+                        continue;
+                    }
+                    for (l, left) in left_cell {
+                        for &(idx, source, r) in by_left.get(l).into_iter().flatten() {
+                            if let Some(right) = right_cell.get(&r) {
+                                let cost = self.rules[idx].cost + left.cost + right.cost;
+                                Self::relax(&mut cell, source, cost, Back::Split(idx, split));
+                            }
+                        }
+                    }
+                }
+                row.push(cell);
+            }
+            chart.push(row);
+        }
+
+        let entry = chart[n - 1][0].get(&start)?;
+        Some(Parse {
+            cost: entry.cost,
+            tree: self.build_tree(&chart, &nonterminals, words, start, n, 0),
+        })
+    }
+
+    fn relax(cell: &mut HashMap<usize, Entry>, symbol: usize, cost: f64, back: Back) {
+        match cell.get(&symbol) {
+            Some(entry) if entry.cost <= cost => {}
+            _ => {
+                cell.insert(symbol, Entry { cost, back });
+            }
+        }
+    }
+
+    fn build_tree(
+        &self,
+        chart: &[Vec<HashMap<usize, Entry>>],
+        nonterminals: &HashMap<&str, usize>,
+        words: &[String],
+        symbol: usize,
+        len: usize,
+        begin: usize,
+    ) -> ParseTree {
+        let entry = &chart[len - 1][begin][&symbol];
+        match entry.back {
+            Back::Terminal(idx) => ParseTree::Terminal {
+                symbol: self.rules[idx].source.to_owned(),
+                word: words[begin].to_owned(),
+            },
+            Back::Split(idx, split) => {
+                let rule = &self.rules[idx];
+                let left = nonterminals[rule.target[0].as_str()];
+                let right = nonterminals[rule.target[1].as_str()];
+                ParseTree::Branch {
+                    symbol: rule.source.to_owned(),
+                    left: Box::new(self.build_tree(chart, nonterminals, words, left, split, begin)),
+                    right: Box::new(self.build_tree(
+                        chart,
+                        nonterminals,
+                        words,
+                        right,
+                        len - split,
+                        begin + split,
+                    )),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::{CNFGrammar, CNFRule, Grammar, ParseTree, WeightedRule};
+
+    fn words(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_owned()).collect()
+    }
+
+    fn grammar(rules: &[(&str, &str, f64)]) -> Grammar<WeightedRule> {
+        let mut grammar = Grammar::new();
+        for (source, target, cost) in rules {
+            grammar.add_rule(&WeightedRule {
+                source: source.to_string(),
+                target: target.to_string(),
+                cost: *cost,
+            });
+        }
+        grammar
+    }
+
+    #[test]
+    fn nested_pairs() {
+        let cnf = grammar(&[("S", "a S b", 1.0), ("S", "a b", 1.0)]).convert_to_cnf("S", words);
+        assert_eq!(Some(2.0), cnf.parse("S", &words("a a b b")).map(|p| p.cost));
+        assert!(cnf.recognise("S", &words("a a a b b b")));
+        assert!(!cnf.recognise("S", &words("a b b")));
+        assert!(!cnf.recognise("S", &words("b a")));
+    }
+
+    #[test]
+    fn cheapest_derivation() {
+        let cnf = grammar(&[("S", "S S", 1.0), ("S", "x", 1.0), ("S", "x x", 1.0)])
+            .convert_to_cnf("S", words);
+        assert_eq!(Some(1.0), cnf.parse("S", &words("x x")).map(|p| p.cost));
+        assert_eq!(Some(3.0), cnf.parse("S", &words("x x x")).map(|p| p.cost));
+    }
+
+    #[test]
+    fn fewest_replacements() {
+        // Puzzle example for day 19: the molecule atoms are quoted terminals
+        let cnf = grammar(&[
+            ("e", "H", 1.0),
+            ("e", "O", 1.0),
+            ("H", "H O", 1.0),
+            ("H", "O H", 1.0),
+            ("O", "H H", 1.0),
+            ("H", "'H'", 0.0),
+            ("O", "'O'", 0.0),
+        ])
+        .convert_to_cnf("e", words);
+        assert_eq!(
+            Some(3.0),
+            cnf.parse("e", &words("'H' 'O' 'H'")).map(|p| p.cost)
+        );
+        assert_eq!(
+            Some(6.0),
+            cnf.parse("e", &words("'H' 'O' 'H' 'O' 'H' 'O'"))
+                .map(|p| p.cost)
+        );
+    }
+
+    #[test]
+    fn nullable() {
+        let cnf = grammar(&[("S", "a S", 1.0), ("S", "", 1.0)]).convert_to_cnf("S", words);
+        assert_eq!(Some(1.0), cnf.parse("S", &words("")).map(|p| p.cost));
+        assert_eq!(Some(2.0), cnf.parse("S", &words("a")).map(|p| p.cost));
+        assert_eq!(Some(3.0), cnf.parse("S", &words("a a")).map(|p| p.cost));
+        assert!(!cnf.recognise("S", &words("b")));
+        // Only the start symbol keeps its empty rule
+        assert!(cnf
+            .rules()
+            .all(|rule| !rule.target.is_empty() || rule.source == "S"));
+
+        // Nullable symbols inside longer rules, and a nullable symbol that isn't the start
+        let cnf = grammar(&[("S", "A b A c A", 1.0), ("A", "a A", 1.0), ("A", "", 0.5)])
+            .convert_to_cnf("S", words);
+        assert_eq!(Some(2.5), cnf.parse("S", &words("b c")).map(|p| p.cost));
+        assert_eq!(Some(3.5), cnf.parse("S", &words("a b c")).map(|p| p.cost));
+        assert!(cnf.recognise("S", &words("b a a c a")));
+        assert!(!cnf.recognise("S", &words("")));
+        assert!(!cnf.recognise("S", &words("b")));
+        assert!(cnf.rules().all(|rule| !rule.target.is_empty()));
+    }
+
+    #[test]
+    fn most_probable_parse() {
+        let mut cnf = CNFGrammar::new();
+        for (source, target, probability) in [
+            ("S", "NP VP", 1.0),
+            ("VP", "V NP", 0.7),
+            ("VP", "VP PP", 0.3),
+            ("NP", "NP PP", 0.4),
+            ("PP", "P NP", 1.0),
+            ("NP", "they", 0.3),
+            ("NP", "fish", 0.3),
+            ("V", "saw", 1.0),
+            ("P", "with", 1.0),
+        ] {
+            cnf.add_rule(&CNFRule::with_probability(
+                source,
+                &words(target),
+                probability,
+            ));
+        }
+        let parse = cnf.parse("S", &words("they saw fish with fish")).unwrap();
+        // NP -> NP PP (0.7 * 0.4) beats VP -> VP PP (0.3 * 0.7)
+        let expected = 0.3 * 0.7 * 0.4 * 0.3 * 0.3;
+        assert!((parse.probability() - expected).abs() < 1e-12);
+        match parse.tree {
+            ParseTree::Branch { right, .. } => match *right {
+                ParseTree::Branch { left, .. } => assert_eq!("V", left.symbol()),
+                _ => panic!("expected VP to branch"),
+            },
+            _ => panic!("expected S to branch"),
+        }
+    }
+}
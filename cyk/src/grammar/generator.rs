@@ -0,0 +1,233 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{Analysis, CNFGrammar, Grammar, Rule};
+
+// How many times to retry a derivation which came out too long (or empty).
+const ATTEMPTS: usize = 100;
+
+pub struct Generator {
+    start_symbol: String,
+    productions: Vec<(String, Vec<String>)>,
+    terminals: Vec<String>,
+    // Fewest levels of rule application needed to reach only terminals
+    min_depth: HashMap<String, usize>,
+    rng: StdRng,
+}
+
+impl Generator {
+    pub fn new<T, F>(grammar: &Grammar<T>, start_symbol: &str, splitter: F, seed: u64) -> Self
+    where
+        T: Debug + Clone + Rule,
+        F: Fn(&str) -> Vec<String>,
+    {
+        let productions = grammar.productions(splitter);
+        let analysis = Analysis::new(start_symbol, &productions);
+        let mut generator = Self {
+            start_symbol: start_symbol.to_owned(),
+            productions,
+            terminals: analysis.terminals.iter().cloned().collect(),
+            min_depth: HashMap::new(),
+            rng: StdRng::seed_from_u64(seed),
+        };
+        loop {
+            let mut changed = false;
+            for (source, target) in &generator.productions {
+                if let Some(depth) = generator.rule_depth(target) {
+                    if generator.min_depth.get(source).is_none_or(|d| depth < *d) {
+                        generator.min_depth.insert(source.to_owned(), depth);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        generator
+    }
+
+    // Derive a random non-empty string of terminals from the start symbol, using at most
+    // max_depth levels of rule application and producing at most max_len terminals.
+    pub fn generate(&mut self, max_depth: usize, max_len: usize) -> Option<Vec<String>> {
+        let start_symbol = self.start_symbol.to_owned();
+        if self
+            .min_depth
+            .get(&start_symbol)
+            .is_none_or(|d| *d > max_depth)
+        {
+            return None;
+        }
+        for _ in 0..ATTEMPTS {
+            let mut words = Vec::new();
+            self.expand(&start_symbol, max_depth, &mut words);
+            if !words.is_empty() && words.len() <= max_len {
+                return Some(words);
+            }
+        }
+        None
+    }
+
+    // Apply one random edit: delete, insert or replace a terminal, or swap two neighbours.
+    pub fn mutate(&mut self, words: &[String]) -> Vec<String> {
+        let mut mutant = words.to_vec();
+        if self.terminals.is_empty() {
+            return mutant;
+        }
+        let terminal = self.terminals.choose(&mut self.rng).unwrap().to_owned();
+        match self.rng.gen_range(0..4) {
+            0 if !mutant.is_empty() => {
+                mutant.remove(self.rng.gen_range(0..mutant.len()));
+            }
+            1 if !mutant.is_empty() => {
+                let idx = self.rng.gen_range(0..mutant.len());
+                mutant[idx] = terminal;
+            }
+            2 if mutant.len() > 1 => {
+                let idx = self.rng.gen_range(0..mutant.len() - 1);
+                mutant.swap(idx, idx + 1);
+            }
+            _ => {
+                let idx = self.rng.gen_range(0..=mutant.len());
+                mutant.insert(idx, terminal);
+            }
+        }
+        mutant
+    }
+
+    // A mutation of words which the grammar does not accept, if one is found.
+    pub fn near_miss(&mut self, words: &[String], cnf_grammar: &CNFGrammar) -> Option<Vec<String>> {
+        for _ in 0..ATTEMPTS {
+            let mutant = self.mutate(words);
+            if !cnf_grammar.recognise(&self.start_symbol, &mutant) {
+                return Some(mutant);
+            }
+        }
+        None
+    }
+
+    fn rule_depth(&self, target: &[String]) -> Option<usize> {
+        let mut depth = 0;
+        for symbol in target {
+            if self.is_terminal(symbol) {
+                continue;
+            }
+            depth = depth.max(*self.min_depth.get(symbol)?);
+        }
+        Some(depth + 1)
+    }
+
+    fn is_terminal(&self, symbol: &str) -> bool {
+        !self.productions.iter().any(|(source, _)| source == symbol)
+    }
+
+    fn expand(&mut self, symbol: &str, depth: usize, words: &mut Vec<String>) {
+        if self.is_terminal(symbol) {
+            words.push(symbol.to_owned());
+            return;
+        }
+        let choices = self
+            .productions
+            .iter()
+            .filter(|(source, target)| {
+                source == symbol && self.rule_depth(target).is_some_and(|d| d <= depth)
+            })
+            .map(|(_, target)| target.to_owned())
+            .collect::<Vec<_>>();
+        let target = choices.choose(&mut self.rng).unwrap().to_owned();
+        for symbol in &target {
+            self.expand(symbol, depth - 1, words);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::bnf;
+
+    fn balanced(words: &[String]) -> bool {
+        let mut open = 0;
+        for word in words {
+            match word.as_str() {
+                "\"(\"" => open += 1,
+                "\")\"" if open > 0 => open -= 1,
+                _ => return false,
+            }
+        }
+        open == 0
+    }
+
+    #[test]
+    fn generate_then_parse() -> anyhow::Result<()> {
+        let grammar = bnf::parse(
+            r#"
+            expr ::= term { "+" term } ;
+            term ::= factor { "*" factor } ;
+            factor ::= "x" | "y" | "(" expr ")" ;
+            "#,
+        )?;
+        let cnf_grammar = grammar.convert_to_cnf("expr", bnf::symbols);
+        let mut generator = Generator::new(&grammar, "expr", bnf::symbols, 2022);
+        for _ in 0..100 {
+            let words = generator.generate(8, 30).unwrap();
+            assert!(words.len() <= 30);
+            assert!(cnf_grammar.recognise("expr", &words), "{words:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn generate_then_parse_nullable() -> anyhow::Result<()> {
+        let grammar = bnf::parse(r#"S ::= "a" S | "" ; T ::= "(" S ")" T | S ;"#)?;
+        for start_symbol in ["S", "T"] {
+            let cnf_grammar = grammar.convert_to_cnf(start_symbol, bnf::symbols);
+            let mut generator = Generator::new(&grammar, start_symbol, bnf::symbols, 3);
+            for _ in 0..50 {
+                let words = generator.generate(8, 30).unwrap();
+                assert!(cnf_grammar.recognise(start_symbol, &words), "{words:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn near_misses_are_rejected() -> anyhow::Result<()> {
+        let grammar = bnf::parse(r#"S ::= "(" ")" | "(" S ")" | S S ;"#)?;
+        let cnf_grammar = grammar.convert_to_cnf("S", bnf::symbols);
+        let mut generator = Generator::new(&grammar, "S", bnf::symbols, 7);
+        for _ in 0..50 {
+            let words = generator.generate(6, 20).unwrap();
+            assert!(balanced(&words));
+            assert!(cnf_grammar.recognise("S", &words));
+            let mutant = generator.near_miss(&words, &cnf_grammar).unwrap();
+            assert!(!balanced(&mutant), "{words:?} => {mutant:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn seeded() -> anyhow::Result<()> {
+        let grammar = bnf::parse(r#"S ::= "a" S | "b" S | "c" ;"#)?;
+        let sentences = |seed| {
+            let mut generator = Generator::new(&grammar, "S", bnf::symbols, seed);
+            (0..10)
+                .map(|_| generator.generate(10, 100))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sentences(1), sentences(1));
+        assert_ne!(sentences(1), sentences(2));
+        Ok(())
+    }
+
+    #[test]
+    fn depth_bound() -> anyhow::Result<()> {
+        let grammar = bnf::parse(r#"S ::= "(" S ")" | "(" T ")" ; T ::= "x" ;"#)?;
+        let mut generator = Generator::new(&grammar, "S", bnf::symbols, 1);
+        assert_eq!(None, generator.generate(1, 100));
+        assert_eq!(3, generator.generate(2, 100).unwrap().len());
+        assert!(generator.generate(5, 100).unwrap().len() <= 9);
+        Ok(())
+    }
+}
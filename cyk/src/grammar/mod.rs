@@ -1,10 +1,28 @@
-use std::{collections::HashSet, fmt::Debug, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    str::FromStr,
+};
 
 use regex::Regex;
 
+pub use analysis::Analysis;
+pub use chart::{Parse, ParseTree};
+
+pub mod bnf;
+pub mod generator;
+
+mod analysis;
+mod chart;
+
 pub trait Rule {
     fn source(&self) -> &str;
     fn target(&self) -> &str;
+
+    // Cost of applying this rule once in a derivation.
+    fn cost(&self) -> f64 {
+        1.0
+    }
 }
 
 #[derive(Debug)]
@@ -12,6 +30,12 @@ pub struct Grammar<T> {
     rules: Vec<T>,
 }
 
+impl<T: Debug + Clone + Rule> Default for Grammar<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Debug + Clone + Rule> Grammar<T> {
     pub fn new() -> Self {
         Self { rules: Vec::new() }
@@ -21,41 +45,206 @@ impl<T: Debug + Clone + Rule> Grammar<T> {
         self.rules.push(rule.clone());
     }
 
-    pub fn convert_to_cnf<F>(&self, _start_symbol: &str, splitter: F) -> CNFGrammar
+    pub fn analyse<F>(&self, start_symbol: &str, splitter: F) -> Analysis
     where
         F: Fn(&str) -> Vec<String>,
     {
-        let rules = self
-            .rules
-            .iter()
-            .map(|rule| {
-                let target_chain = splitter(rule.target());
-                CNFRule::new(rule.source(), &target_chain)
-            })
-            .collect::<Vec<_>>();
-        let known_symbols = rules
+        Analysis::new(start_symbol, &self.productions(splitter))
+    }
+
+    pub fn convert_to_cnf<F>(&self, start_symbol: &str, splitter: F) -> CNFGrammar
+    where
+        F: Fn(&str) -> Vec<String>,
+    {
+        let analysis = self.analyse(start_symbol, &splitter);
+        log::debug!(
+            "known symbols: {:?} {:?}",
+            analysis.nonterminals,
+            analysis.terminals
+        );
+        for diagnostic in analysis.diagnostics() {
+            log::debug!("{diagnostic}");
+        }
+
+        let mut symbols = analysis
+            .nonterminals
             .iter()
-            .flat_map(|rule| {
-                std::iter::once(rule.source.to_owned()).chain(rule.target.clone().into_iter())
-            })
+            .chain(analysis.terminals.iter())
+            .cloned()
             .collect::<HashSet<_>>();
+        let mut fresh = |name: String| {
+            let mut name = name;
+            while !symbols.insert(name.clone()) {
+                name.push('\'');
+            }
+            name
+        };
 
-        log::debug!("known symbols: {known_symbols:?}");
-        let mut cnf_grammar = CNFGrammar::new();
+        // Move terminals out of long rules, and break those rules into pairs
+        let mut rules = Vec::new();
+        let mut terminal_wrappers = HashMap::new();
+        let mut helpers = 0;
         for rule in &self.rules {
-            let target_chain = splitter(rule.target());
-            if (1..=2).contains(&target_chain.len()) {
-                cnf_grammar.add_rule(&CNFRule::new(rule.source(), &target_chain));
-            } else {
-                log::error!(
-                    "unhandled source rule (len: {}): {:?}",
-                    target_chain.len(),
-                    rule
-                );
+            let target = splitter(rule.target());
+            if target.len() <= 1 {
+                rules.push(CNFRule::new(rule.source(), &target, rule.cost()));
+                continue;
+            }
+            let mut chain = Vec::new();
+            for symbol in target {
+                if !analysis.is_terminal(&symbol) {
+                    chain.push(symbol);
+                    continue;
+                }
+                let wrapper = match terminal_wrappers.get(&symbol) {
+                    Some(wrapper) => String::clone(wrapper),
+                    None => {
+                        let wrapper = fresh(format!("T({symbol})"));
+                        rules.push(CNFRule::new(&wrapper, &[symbol.to_owned()], 0.0));
+                        terminal_wrappers.insert(symbol, wrapper.to_owned());
+                        wrapper
+                    }
+                };
+                chain.push(wrapper);
             }
+            let mut source = rule.source().to_owned();
+            let mut cost = rule.cost();
+            let mut rest = &chain[..];
+            while rest.len() > 2 {
+                helpers += 1;
+                let helper = fresh(format!("{}~{helpers}", rule.source()));
+                rules.push(CNFRule::new(
+                    &source,
+                    &[rest[0].to_owned(), helper.to_owned()],
+                    cost,
+                ));
+                source = helper;
+                cost = 0.0;
+                rest = &rest[1..];
+            }
+            rules.push(CNFRule::new(&source, rest, cost));
+        }
+
+        // Remove empty rules, adding a copy of each pair without each nullable symbol at
+        // the cost of its cheapest empty derivation. Only the start symbol keeps one.
+        let empty_costs = Self::empty_costs(&rules);
+        let mut without_empty = Vec::new();
+        for rule in rules {
+            if let [left, right] = &rule.target[..] {
+                for (dropped, kept) in [(left, right), (right, left)] {
+                    if let Some(cost) = empty_costs.get(dropped.as_str()) {
+                        without_empty.push(CNFRule::new(
+                            &rule.source,
+                            &[kept.to_owned()],
+                            rule.cost + cost,
+                        ));
+                    }
+                }
+            }
+            if !rule.target.is_empty() {
+                without_empty.push(rule);
+            }
+        }
+        let rules = without_empty;
+
+        // Replace unit rules A -> B by A -> x for each non-unit B -> x
+        let nonterminals = rules
+            .iter()
+            .map(|rule| rule.source.as_str())
+            .chain(analysis.nonterminals.iter().map(String::as_str))
+            .collect::<BTreeSet<_>>();
+        let is_unit = |rule: &CNFRule| {
+            rule.target.len() == 1 && nonterminals.contains(rule.target[0].as_str())
+        };
+        let mut unit_costs: BTreeMap<&str, HashMap<&str, f64>> = nonterminals
+            .iter()
+            .map(|&symbol| (symbol, HashMap::from([(symbol, 0.0)])))
+            .collect();
+        for _ in 0..nonterminals.len() {
+            let mut changed = false;
+            for rule in rules.iter().filter(|rule| is_unit(rule)) {
+                for reachable in unit_costs.values_mut() {
+                    let Some(cost) = reachable.get(rule.source.as_str()).map(|c| c + rule.cost)
+                    else {
+                        continue;
+                    };
+                    let best = reachable
+                        .entry(rule.target[0].as_str())
+                        .or_insert(f64::INFINITY);
+                    if cost < *best {
+                        *best = cost;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut cnf_grammar = CNFGrammar::new();
+        let mut known: HashMap<(String, Vec<String>), usize> = HashMap::new();
+        for (source, reachable) in unit_costs {
+            for rule in rules.iter().filter(|rule| !is_unit(rule)) {
+                let Some(cost) = reachable.get(rule.source.as_str()) else {
+                    continue;
+                };
+                let rule = CNFRule::new(source, &rule.target, cost + rule.cost);
+                match known.get(&(rule.source.to_owned(), rule.target.to_owned())) {
+                    Some(&idx) if cnf_grammar.rules[idx].cost <= rule.cost => {}
+                    Some(&idx) => cnf_grammar.rules[idx] = rule,
+                    None => {
+                        known.insert(
+                            (rule.source.to_owned(), rule.target.to_owned()),
+                            cnf_grammar.rules.len(),
+                        );
+                        cnf_grammar.add_rule(&rule);
+                    }
+                }
+            }
+        }
+        if let Some(&cost) = empty_costs.get(start_symbol) {
+            cnf_grammar.add_rule(&CNFRule::new(start_symbol, &[], cost));
         }
         cnf_grammar
     }
+
+    // The cheapest way for each nullable symbol to derive nothing
+    fn empty_costs(rules: &[CNFRule]) -> HashMap<String, f64> {
+        let mut costs: HashMap<String, f64> = HashMap::new();
+        loop {
+            let mut changed = false;
+            for rule in rules {
+                let Some(cost) = rule
+                    .target
+                    .iter()
+                    .map(|symbol| costs.get(symbol.as_str()))
+                    .sum::<Option<f64>>()
+                    .map(|cost| cost + rule.cost)
+                else {
+                    continue;
+                };
+                let best = costs.entry(rule.source.to_owned()).or_insert(f64::INFINITY);
+                if cost < *best {
+                    *best = cost;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break costs;
+            }
+        }
+    }
+
+    fn productions<F>(&self, splitter: F) -> Vec<(String, Vec<String>)>
+    where
+        F: Fn(&str) -> Vec<String>,
+    {
+        self.rules
+            .iter()
+            .map(|rule| (rule.source().to_owned(), splitter(rule.target())))
+            .collect()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -68,11 +257,11 @@ impl CNFGrammar {
         self.rules.iter()
     }
 
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self { rules: Vec::new() }
     }
 
-    fn add_rule(&mut self, rule: &CNFRule) {
+    pub fn add_rule(&mut self, rule: &CNFRule) {
         self.rules.push(rule.clone());
     }
 }
@@ -125,17 +314,46 @@ impl FromStr for SimpleRule {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct WeightedRule {
+    pub source: String,
+    pub target: String,
+    pub cost: f64,
+}
+
+impl Rule for WeightedRule {
+    fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn target(&self) -> &str {
+        &self.target
+    }
+
+    fn cost(&self) -> f64 {
+        self.cost
+    }
+}
+
+// Either A -> B C over nonterminals, or A -> a for a terminal.
 #[derive(Debug, Clone)]
 pub struct CNFRule {
     pub source: String,
     pub target: Vec<String>,
+    pub cost: f64,
 }
 
 impl CNFRule {
-    fn new(source: &str, target: &Vec<String>) -> Self {
+    pub fn new(source: &str, target: &[String], cost: f64) -> Self {
         Self {
             source: source.to_owned(),
             target: target.to_owned(),
+            cost,
         }
     }
+
+    // Probabilistic grammars are parsed as minimum cost, using -ln(p) as the cost.
+    pub fn with_probability(source: &str, target: &[String], probability: f64) -> Self {
+        Self::new(source, target, -probability.ln())
+    }
 }
@@ -1,12 +1,12 @@
 use anyhow::Result;
-use grammar::{Grammar, SimpleRule};
+use grammar::{Analysis, Grammar, SimpleRule};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::str::FromStr;
 
-mod grammar;
+pub mod grammar;
 
 pub fn load(filename: &str) -> Result<Solution> {
     let file = File::open(filename)?;
@@ -68,6 +68,14 @@ impl Solution {
     pub fn answer_part2(&self) -> Option<u64> {
         self.answer_part2
     }
+
+    pub fn report(&self) -> Analysis {
+        self.grammar.analyse("e", Self::split)
+    }
+
+    pub fn to_bnf(&self) -> String {
+        self.grammar.to_bnf(Self::split)
+    }
 }
 
 impl Solution {
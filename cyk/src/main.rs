@@ -10,6 +10,17 @@ fn main() -> Result<()> {
     //let filename = "input/cyk.input";
     let filename = "input/day19.input";
     let mut solution = load(filename)?;
+    match std::env::args().nth(1).as_deref() {
+        Some("report") => {
+            print!("{}", solution.report());
+            return Ok(());
+        }
+        Some("bnf") => {
+            print!("{}", solution.to_bnf());
+            return Ok(());
+        }
+        _ => {}
+    }
     info!(
         "{}{}: {:?}",
         Paint::masked("🎄 "),
@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use cyk::grammar::{Grammar, WeightedRule};
 use regex::Regex;
 use std::collections::HashSet;
 use std::fs::File;
@@ -111,48 +112,74 @@ impl Solution {
     }
 
     fn analyse_part2(&mut self) -> Option<u64> {
-        //None
-        /*
-        for idx in 0..self.molecule.len() {
-            for (source, target) in &self.replacements {
-                if idx + target.len() <= self.molecule.len() && target == &self.molecule[idx..idx+target.len()] {
-                    log::debug!("{} => {}", source, target);
-                }
+        match self.fewest_steps() {
+            Ok(steps) => Some(steps),
+            Err(e) => {
+                log::error!("{e:#}");
+                None
             }
         }
-        self.analyse_part2_step(self.molecule.clone(), 0)
-        */
-        self.analyse_part2_step(self.molecule.clone(), 0)
     }
 
-    fn analyse_part2_step(&self, cur_molecule: String, num_changes: u64) -> Option<u64> {
-        // Match
-        if cur_molecule == "e" {
-            return Some(num_changes);
-        }
-        if cur_molecule.len() <= 1 {
-            panic!();
-        }
-        let mut best_source: Option<&String> = None;
-        let mut best_target: Option<&String> = None;
-        for (source, target) in &self.replacements {
-            if let Some(b) = best_target {
-                if b.len() > target.len() {
-                    continue;
-                }
-            }
-            if cur_molecule.contains(target) {
-                best_target = Some(target);
-                best_source = Some(source);
+    // Each replacement is a rule costing one step; every element which can be replaced
+    // also derives its own atom for free, so the cheapest parse of the molecule from
+    // e is the fewest number of steps.
+    fn fewest_steps(&self) -> Result<u64> {
+        let sources = self
+            .replacements
+            .iter()
+            .map(|(source, _)| source.as_str())
+            .collect::<HashSet<_>>();
+        let symbol = |atom: String| {
+            if sources.contains(atom.as_str()) {
+                atom
+            } else {
+                format!("'{atom}'")
             }
+        };
+
+        let mut grammar = Grammar::new();
+        for (source, target) in &self.replacements {
+            grammar.add_rule(&WeightedRule {
+                source: source.to_owned(),
+                target: Self::atoms(target)
+                    .into_iter()
+                    .map(symbol)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                cost: 1.0,
+            });
+        }
+        for source in &sources {
+            grammar.add_rule(&WeightedRule {
+                source: source.to_string(),
+                target: format!("'{source}'"),
+                cost: 0.0,
+            });
         }
-        match best_target {
-            Some(b) => {
-                let next_molecule = cur_molecule.replacen(b, best_source.unwrap(), 1);
-                self.analyse_part2_step(next_molecule, num_changes + 1)
+        let cnf_grammar =
+            grammar.convert_to_cnf("e", |s| s.split_whitespace().map(str::to_owned).collect());
+
+        let words = Self::atoms(&self.molecule)
+            .into_iter()
+            .map(|atom| format!("'{atom}'"))
+            .collect::<Vec<_>>();
+        let parse = cnf_grammar
+            .parse("e", &words)
+            .with_context(|| format!("molecule {} cannot be made from e", self.molecule))?;
+        Ok(parse.cost.round() as u64)
+    }
+
+    // Split a molecule into its elements, each starting with an uppercase letter
+    fn atoms(molecule: &str) -> Vec<String> {
+        let mut atoms: Vec<String> = Vec::new();
+        for c in molecule.chars() {
+            match atoms.last_mut() {
+                Some(atom) if !c.is_uppercase() => atom.push(c),
+                _ => atoms.push(c.to_string()),
             }
-            None => panic!(),
         }
+        atoms
     }
 }
 
@@ -184,3 +211,37 @@ impl FromStr for Replacement {
         Ok(Self { source, target })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn solution(replacements: &[(&str, &str)], molecule: &str) -> Solution {
+        let mut solution = Solution::new();
+        for (source, target) in replacements {
+            solution.add_replacement(source.to_string(), target.to_string());
+        }
+        solution.set_molecule(molecule.to_string());
+        solution
+    }
+
+    const REPLACEMENTS: [(&str, &str); 5] = [
+        ("e", "H"),
+        ("e", "O"),
+        ("H", "HO"),
+        ("H", "OH"),
+        ("O", "HH"),
+    ];
+
+    #[test]
+    fn fewest_steps() {
+        assert_eq!(3, solution(&REPLACEMENTS, "HOH").fewest_steps().unwrap());
+        assert_eq!(6, solution(&REPLACEMENTS, "HOHOHO").fewest_steps().unwrap());
+    }
+
+    #[test]
+    fn not_derivable() {
+        assert!(solution(&REPLACEMENTS, "HOC").fewest_steps().is_err());
+        assert_eq!(None, solution(&REPLACEMENTS, "OO").analyse_part2());
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use combat::{damage, Side, Stats};
+
+// How a fight ends. Turns count the attacks of both sides, the player's first.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Outcome {
+    pub winner: Side,
+    pub turn: u64,
+    pub player_hit_points: u64,
+    pub boss_hit_points: u64,
+}
+
+// One attack
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Attack {
+    pub side: Side,
+    pub damage: u64,
+    pub armour: u64,
+    // Left to the side attacked
+    pub hit_points: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    pub attacks: Vec<Attack>,
+    pub outcome: Outcome,
+}
+
+// Settle a fight without playing it out
+pub fn resolve(player: &Stats, boss: &Stats) -> Outcome {
+    let attacks = player.turns_to_kill(boss);
+    let survived = boss.turns_to_kill(player);
+    if attacks <= survived {
+        Outcome {
+            winner: Side::Player,
+            turn: 2 * attacks - 1,
+            player_hit_points: player.hit_points - (attacks - 1) * boss.damage_to(player),
+            boss_hit_points: 0,
+        }
+    } else {
+        Outcome {
+            winner: Side::Boss,
+            turn: 2 * survived,
+            player_hit_points: 0,
+            boss_hit_points: boss.hit_points - survived * player.damage_to(boss),
+        }
+    }
+}
+
+// Play a fight out an attack at a time
+pub fn transcript(player: &Stats, boss: &Stats) -> Transcript {
+    let (mut player, mut boss) = (*player, *boss);
+    let mut attacks = Vec::new();
+    loop {
+        let (side, attacker, defender) = match attacks.len() % 2 {
+            0 => (Side::Player, &player, &mut boss),
+            _ => (Side::Boss, &boss, &mut player),
+        };
+        let damage = attacker.damage_to(defender);
+        defender.hit_points = defender.hit_points.saturating_sub(damage);
+        attacks.push(Attack {
+            side,
+            damage: attacker.damage,
+            armour: defender.armour,
+            hit_points: defender.hit_points,
+        });
+        if defender.hit_points == 0 {
+            let outcome = Outcome {
+                winner: side,
+                turn: attacks.len() as u64,
+                player_hit_points: player.hit_points,
+                boss_hit_points: boss.hit_points,
+            };
+            return Transcript { attacks, outcome };
+        }
+    }
+}
+
+// In the words of the puzzle
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for attack in &self.attacks {
+            let target = match attack.side {
+                Side::Player => Side::Boss,
+                Side::Boss => Side::Player,
+            };
+            let dealt = damage(attack.damage, attack.armour);
+            write!(f, "The {} deals ", attack.side)?;
+            if attack.damage > attack.armour {
+                write!(f, "{}-{} = ", attack.damage, attack.armour)?;
+            }
+            writeln!(
+                f,
+                "{dealt} damage; the {target} goes down to {} hit points.",
+                attack.hit_points
+            )?;
+        }
+        let outcome = &self.outcome;
+        write!(f, "The {} wins on turn {}", outcome.winner, outcome.turn)?;
+        match outcome.winner {
+            Side::Player => writeln!(f, " with {} hit points left.", outcome.player_hit_points),
+            Side::Boss => writeln!(f, " with {} hit points left.", outcome.boss_hit_points),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        let player = Stats {
+            hit_points: 8,
+            damage: 5,
+            armour: 5,
+        };
+        let boss = Stats {
+            hit_points: 12,
+            damage: 7,
+            armour: 2,
+        };
+        let transcript = transcript(&player, &boss);
+        assert_eq!(
+            "\
+The player deals 5-2 = 3 damage; the boss goes down to 9 hit points.
+The boss deals 7-5 = 2 damage; the player goes down to 6 hit points.
+The player deals 5-2 = 3 damage; the boss goes down to 6 hit points.
+The boss deals 7-5 = 2 damage; the player goes down to 4 hit points.
+The player deals 5-2 = 3 damage; the boss goes down to 3 hit points.
+The boss deals 7-5 = 2 damage; the player goes down to 2 hit points.
+The player deals 5-2 = 3 damage; the boss goes down to 0 hit points.
+The player wins on turn 7 with 2 hit points left.
+",
+            transcript.to_string()
+        );
+        assert_eq!(transcript.outcome, resolve(&player, &boss));
+    }
+
+    #[test]
+    fn agrees_with_transcript() {
+        for hit_points in 1..30 {
+            for damage in 0..8 {
+                for armour in 0..8 {
+                    let player = Stats {
+                        hit_points,
+                        damage,
+                        armour,
+                    };
+                    let boss = Stats {
+                        hit_points: 20,
+                        damage: 6,
+                        armour: 3,
+                    };
+                    assert_eq!(
+                        transcript(&player, &boss).outcome,
+                        resolve(&player, &boss),
+                        "{player:?}"
+                    );
+                }
+            }
+        }
+    }
+}
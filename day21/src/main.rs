@@ -1,13 +1,45 @@
-use anyhow::Result;
-use day21::load;
+use anyhow::{anyhow, Context, Result};
+use day21::{load, load_characters, Loadout, Shop, Solution};
 use env_logger::Env;
 use log::{error, info};
 use yansi::Paint;
 
+// The shop for a variant of the game
+const SHOP: &str = "input/day21.shop";
+
 fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
     let mut solution = load("input/day21.input")?;
+    if std::path::Path::new(SHOP).exists() {
+        solution.set_shop(Shop::load(SHOP)?);
+    }
+    let json = std::env::args().any(|arg| arg == "json");
+    match std::env::args().nth(1).as_deref() {
+        Some("fight") => return fight(&solution),
+        Some("pareto") => {
+            let front = solution.pareto_front();
+            match json {
+                true => println!("{}", front.to_json()),
+                false => print!("{front}"),
+            }
+            return Ok(());
+        }
+        Some("against") => {
+            let filename = std::env::args().nth(2).context("needs a file of bosses")?;
+            let bosses = load_characters(&filename)?
+                .iter()
+                .map(|boss| boss.stats())
+                .collect::<Vec<_>>();
+            match solution.cheapest_against(&bosses) {
+                Some(challenge) if json => println!("{}", challenge.to_json()),
+                Some(challenge) => print!("{challenge}"),
+                None => println!("nothing in the shop beats them all"),
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
     info!(
         "{}{}: {:?}",
         Paint::masked("🎄 "),
@@ -35,3 +67,22 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+// fight <item>, <item>, ...
+fn fight(solution: &Solution) -> Result<()> {
+    let names = std::env::args().skip(2).collect::<Vec<_>>().join(" ");
+    let items = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            solution
+                .shop()
+                .find(name)
+                .ok_or_else(|| anyhow!("the shop has no {name}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let loadout = Loadout { items };
+    println!("{}", solution.fight(&loadout));
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::{resolve, Loadout, Outcome, Side, Solution, Stats};
+
+// A winning loadout, and how well it wins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Point<'a> {
+    pub loadout: Loadout<'a>,
+    pub cost: u64,
+    // Left to the player at the end
+    pub hit_points: u64,
+    // Attacks by both sides, as in Outcome
+    pub turns: u64,
+}
+
+impl Point<'_> {
+    // At least as good in every way, and better in one
+    fn dominates(&self, other: &Point) -> bool {
+        let key = |point: &Point| (point.cost, point.hit_points, point.turns);
+        self.cost <= other.cost
+            && self.hit_points >= other.hit_points
+            && self.turns <= other.turns
+            && key(self) != key(other)
+    }
+}
+
+// The winning loadouts that nothing else beats on cost, hit points left and turns taken,
+// cheapest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Front<'a> {
+    pub points: Vec<Point<'a>>,
+}
+
+impl<'a> Front<'a> {
+    pub fn new(points: Vec<Point<'a>>) -> Self {
+        let mut front = points
+            .iter()
+            .filter(|point| !points.iter().any(|other| other.dominates(point)))
+            .cloned()
+            .collect::<Vec<_>>();
+        front.sort_by_key(|point| (point.cost, point.turns));
+        Self { points: front }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(
+            self.points
+                .iter()
+                .map(|point| {
+                    json!({
+                        "cost": point.cost,
+                        "hit_points": point.hit_points,
+                        "turns": point.turns,
+                        "items": point.loadout.names(),
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for Front<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>6}  items",
+            "cost", "hit points", "turns"
+        )?;
+        for point in &self.points {
+            writeln!(
+                f,
+                "{:>6} {:>10} {:>6}  {}",
+                point.cost,
+                point.hit_points,
+                point.turns,
+                point.loadout.names().join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The cheapest loadout that beats every one of a list of bosses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge<'a> {
+    pub loadout: Loadout<'a>,
+    pub fights: Vec<(Stats, Outcome)>,
+}
+
+impl Challenge<'_> {
+    pub fn to_json(&self) -> Value {
+        let fights = self
+            .fights
+            .iter()
+            .map(|(boss, outcome)| {
+                json!({
+                    "boss": {
+                        "hit_points": boss.hit_points,
+                        "damage": boss.damage,
+                        "armour": boss.armour,
+                    },
+                    "hit_points": outcome.player_hit_points,
+                    "turns": outcome.turn,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "cost": self.loadout.cost(),
+            "items": self.loadout.names(),
+            "fights": fights,
+        })
+    }
+}
+
+impl fmt::Display for Challenge<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} gold: {}",
+            self.loadout.cost(),
+            self.loadout.names().join(", ")
+        )?;
+        writeln!(
+            f,
+            "{:>10} {:>6} {:>6} {:>10} {:>6}",
+            "boss hp", "damage", "armor", "hit points", "turns"
+        )?;
+        for (boss, outcome) in &self.fights {
+            writeln!(
+                f,
+                "{:>10} {:>6} {:>6} {:>10} {:>6}",
+                boss.hit_points, boss.damage, boss.armour, outcome.player_hit_points, outcome.turn
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Solution {
+    // The trade-offs between the loadouts that beat the boss
+    pub fn pareto_front(&self) -> Front<'_> {
+        let points = self
+            .shop()
+            .loadouts()
+            .into_iter()
+            .filter_map(|loadout| {
+                let outcome = resolve(&Self::player(&loadout), self.boss());
+                (outcome.winner == Side::Player).then(|| Point {
+                    cost: loadout.cost(),
+                    hit_points: outcome.player_hit_points,
+                    turns: outcome.turn,
+                    loadout,
+                })
+            })
+            .collect();
+        Front::new(points)
+    }
+
+    pub fn cheapest_against(&self, bosses: &[Stats]) -> Option<Challenge<'_>> {
+        self.shop()
+            .loadouts()
+            .into_iter()
+            .filter_map(|loadout| {
+                let player = Self::player(&loadout);
+                let fights = bosses
+                    .iter()
+                    .map(|boss| (*boss, resolve(&player, boss)))
+                    .collect::<Vec<_>>();
+                fights
+                    .iter()
+                    .all(|(_, outcome)| outcome.winner == Side::Player)
+                    .then_some(Challenge { loadout, fights })
+            })
+            .min_by_key(|challenge| challenge.loadout.cost())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boss(hit_points: u64, damage: u64, armour: u64) -> Stats {
+        Stats {
+            hit_points,
+            damage,
+            armour,
+        }
+    }
+
+    #[test]
+    fn front() {
+        let mut solution = Solution::new();
+        for line in ["Hit Points: 103", "Damage: 9", "Armor: 2"] {
+            solution.update_boss(line).unwrap();
+        }
+        let front = solution.pareto_front();
+        // The cheapest win is the answer to part 1
+        assert_eq!(121, front.points[0].cost);
+        for (idx, point) in front.points.iter().enumerate() {
+            for other in &front.points[idx + 1..] {
+                assert!(!point.dominates(other) && !other.dominates(point));
+            }
+        }
+        // Nothing cheaper can win faster or with more hit points left
+        assert!(front.points.windows(2).all(|pair| {
+            pair[0].cost == pair[1].cost
+                || pair[0].hit_points < pair[1].hit_points
+                || pair[0].turns > pair[1].turns
+        }));
+        assert_eq!(
+            front.points.len(),
+            front.to_json().as_array().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn against() {
+        let solution = Solution::new();
+        let weak = boss(1, 0, 0);
+        let challenge = solution
+            .cheapest_against(std::slice::from_ref(&weak))
+            .unwrap();
+        assert_eq!(vec!["Dagger"], challenge.loadout.names());
+
+        let bosses = [weak, boss(103, 9, 2), boss(100, 8, 2)];
+        let challenge = solution.cheapest_against(&bosses).unwrap();
+        assert_eq!(3, challenge.fights.len());
+        assert!(challenge.loadout.cost() >= 121);
+        assert_eq!(challenge.loadout.cost(), challenge.to_json()["cost"]);
+
+        assert!(solution.cheapest_against(&[boss(1000, 100, 0)]).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use anyhow::{anyhow, Context, Result};
+use combat::{Bonus, Equipment};
+
+// The shop from the puzzle
+const STANDARD: &str = include_str!("../shop.txt");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub name: String,
+    pub cost: u64,
+    pub damage: u64,
+    pub armour: u64,
+}
+
+// Items of one kind, and how many of them a loadout may have. Unless unique is set the
+// same item can be bought more than once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Category {
+    pub name: String,
+    pub items: Vec<Item>,
+    pub min: usize,
+    pub max: usize,
+    pub unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shop {
+    categories: Vec<Category>,
+}
+
+// One legal choice of items
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loadout<'a> {
+    pub items: Vec<&'a Item>,
+}
+
+impl Loadout<'_> {
+    pub fn cost(&self) -> u64 {
+        self.items.iter().map(|item| item.cost).sum()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.items.iter().map(|item| item.name.as_str()).collect()
+    }
+}
+
+impl Equipment for Item {
+    fn bonus(&self) -> Bonus {
+        Bonus {
+            damage: self.damage,
+            armour: self.armour,
+        }
+    }
+}
+
+impl Equipment for Loadout<'_> {
+    fn bonus(&self) -> Bonus {
+        self.items.iter().map(|item| item.bonus()).sum()
+    }
+}
+
+impl Default for Shop {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl Shop {
+    pub fn standard() -> Self {
+        STANDARD.parse().unwrap()
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        std::fs::read_to_string(filename)?
+            .parse()
+            .with_context(|| filename.to_owned())
+    }
+
+    pub fn categories(&self) -> &[Category] {
+        &self.categories
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Item> {
+        self.categories
+            .iter()
+            .flat_map(|category| &category.items)
+            .find(|item| item.name.eq_ignore_ascii_case(name))
+    }
+
+    // Every loadout the slot rules allow
+    pub fn loadouts(&self) -> Vec<Loadout<'_>> {
+        let mut loadouts = vec![Loadout { items: Vec::new() }];
+        for category in &self.categories {
+            let mut choices = Vec::new();
+            for count in category.min..=category.max {
+                choose(category, count, 0, &mut Vec::new(), &mut choices);
+            }
+            loadouts = loadouts
+                .iter()
+                .flat_map(|loadout| {
+                    choices.iter().map(|choice| {
+                        let mut items = loadout.items.clone();
+                        items.extend(choice.iter().map(|&idx| &category.items[idx]));
+                        Loadout { items }
+                    })
+                })
+                .collect();
+        }
+        loadouts
+    }
+}
+
+// Every way of picking `count` items from the category, without regard to order, from
+// `first` onwards
+fn choose(
+    category: &Category,
+    count: usize,
+    first: usize,
+    chosen: &mut Vec<usize>,
+    choices: &mut Vec<Vec<usize>>,
+) {
+    if chosen.len() == count {
+        choices.push(chosen.clone());
+        return;
+    }
+    for idx in first..category.items.len() {
+        chosen.push(idx);
+        let next = if category.unique { idx + 1 } else { idx };
+        choose(category, count, next, chosen, choices);
+        chosen.pop();
+    }
+}
+
+// Tables in the form of the puzzle's shop, one per category, each headed by
+// `<category>: Cost Damage Armor`, and a `Slots: Min Max Unique` table giving the rules
+// for each category.
+impl std::str::FromStr for Shop {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut categories: Vec<Category> = Vec::new();
+        let mut slots = Vec::new();
+        let mut table = None;
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((name, _)) = line.split_once(':') {
+                if name == "Slots" {
+                    table = None;
+                } else {
+                    categories.push(Category {
+                        name: name.to_owned(),
+                        items: Vec::new(),
+                        min: 0,
+                        max: 0,
+                        unique: true,
+                    });
+                    table = Some(categories.len() - 1);
+                }
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words.len() < 4 {
+                return Err(anyhow!(
+                    "line {}: expected a name and three columns",
+                    number + 1
+                ));
+            }
+            let (name, columns) = words.split_at(words.len() - 3);
+            let name = name.join(" ");
+            let number = number + 1;
+            match table {
+                Some(idx) => {
+                    let value = |s: &str| {
+                        s.parse::<u64>()
+                            .with_context(|| format!("line {number}: bad number {s}"))
+                    };
+                    categories[idx].items.push(Item {
+                        name,
+                        cost: value(columns[0])?,
+                        damage: value(columns[1])?,
+                        armour: value(columns[2])?,
+                    });
+                }
+                None => {
+                    let count = |s: &str| {
+                        s.parse::<usize>()
+                            .with_context(|| format!("line {number}: bad count {s}"))
+                    };
+                    let unique = match columns[2] {
+                        "yes" => true,
+                        "no" => false,
+                        other => {
+                            return Err(anyhow!(
+                                "line {number}: unique should be yes or no, not {other}"
+                            ))
+                        }
+                    };
+                    slots.push((number, name, count(columns[0])?, count(columns[1])?, unique));
+                }
+            }
+        }
+
+        let mut ruled = Vec::new();
+        for (number, name, min, max, unique) in slots {
+            let category = categories
+                .iter_mut()
+                .find(|category| category.name == name)
+                .ok_or_else(|| anyhow!("line {number}: no category {name}"))?;
+            if min > max || (unique && max > category.items.len()) {
+                return Err(anyhow!("line {number}: can't have {min} to {max} {name}"));
+            }
+            (category.min, category.max, category.unique) = (min, max, unique);
+            ruled.push(name);
+        }
+        if let Some(category) = categories
+            .iter()
+            .find(|category| !ruled.contains(&category.name))
+        {
+            return Err(anyhow!("{} has no Slots row", category.name));
+        }
+        Ok(Self { categories })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard() {
+        let shop = Shop::standard();
+        let names = shop
+            .categories()
+            .iter()
+            .map(|c| (c.name.as_str(), c.items.len(), c.min, c.max))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("Weapons", 5, 1, 1), ("Armor", 5, 0, 1), ("Rings", 6, 0, 2)],
+            names
+        );
+        // Rings: none, one of six or two of six
+        assert_eq!(5 * 6 * (1 + 6 + 15), shop.loadouts().len());
+        assert!(shop
+            .loadouts()
+            .iter()
+            .any(|loadout| loadout.names() == vec!["Dagger"]));
+    }
+
+    #[test]
+    fn repeats() -> Result<()> {
+        let shop: Shop = "\
+            Potions: Cost Damage Armor
+            Strength 3 1 0
+            Toughness 5 0 1
+            Slots: Min Max Unique
+            Potions 2 2 no
+        "
+        .parse()?;
+        let costs = shop.loadouts().iter().map(|l| l.cost()).collect::<Vec<_>>();
+        assert_eq!(vec![6, 8, 10], costs);
+        assert!(
+            "Rings: Cost Damage Armor\nSlots: Min Max Unique\nRings 0 1 yes\n"
+                .parse::<Shop>()
+                .is_err()
+        );
+        assert!("Rings: Cost Damage Armor\nRing 1 2\n"
+            .parse::<Shop>()
+            .is_err());
+        let unruled = "\
+            Weapons: Cost Damage Armor
+            Dagger 8 4 0
+            Rings: Cost Damage Armor
+            Damage +1 25 1 0
+            Slots: Min Max Unique
+            Weapons 1 1 yes
+        "
+        .parse::<Shop>();
+        assert_eq!("Rings has no Slots row", unruled.unwrap_err().to_string());
+        Ok(())
+    }
+}
@@ -0,0 +1,505 @@
+use std::fmt;
+
+use combat::{Character, Equipment, Side};
+
+use serde_json::{json, Value};
+
+use crate::Spellbook;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastError {
+    Unaffordable,
+    Active,
+    // The battle is already over
+    Finished,
+}
+
+impl fmt::Display for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unaffordable => write!(f, "not enough mana"),
+            Self::Active => write!(f, "its effect is still active"),
+            Self::Finished => write!(f, "the battle is over"),
+        }
+    }
+}
+
+impl std::error::Error for CastError {}
+
+// What both characters looked like at the start of a turn, and what happened in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub side: Side,
+    pub player_hit_points: u64,
+    pub player_armour: u64,
+    pub player_mana: u64,
+    pub boss_hit_points: u64,
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    pub turns: Vec<Turn>,
+    pub winner: Option<Side>,
+    pub mana_spent: u64,
+}
+
+// A fight between the player and the boss, a round at a time. The player casts a spell
+// each round and the boss attacks, with effects applying at the start of every turn.
+// The player never attacks, so their damage, such as from equipment, is added to the
+// immediate damage of each spell that does some.
+#[derive(Debug, Clone)]
+pub struct Battle<'a> {
+    spellbook: &'a Spellbook,
+    pub player: Character,
+    pub boss: Character,
+    pub mana_spent: u64,
+    // Spells the player has cast so far
+    pub cast: Vec<usize>,
+    // Turns started so far, counting both sides
+    pub turns: usize,
+    // The player loses a hit point at the start of each of their turns
+    pub hard_difficulty: bool,
+    transcript: Option<Transcript>,
+}
+
+impl<'a> Battle<'a> {
+    pub fn new(spellbook: &'a Spellbook, player: Character, boss: Character) -> Self {
+        Self {
+            spellbook,
+            player,
+            boss,
+            mana_spent: 0,
+            cast: Vec::new(),
+            turns: 0,
+            hard_difficulty: false,
+            transcript: None,
+        }
+    }
+
+    pub fn with_hard_difficulty(mut self, hard_difficulty: bool) -> Self {
+        self.hard_difficulty = hard_difficulty;
+        self
+    }
+
+    // The player wearing the item as well as casting spells
+    pub fn equipped(mut self, item: &impl Equipment) -> Self {
+        self.player.equip(item);
+        self
+    }
+
+    // Keep a transcript of the battle from here on
+    pub fn recording(mut self) -> Self {
+        self.transcript = Some(Transcript::default());
+        self
+    }
+
+    pub fn transcript(&self) -> Option<&Transcript> {
+        self.transcript.as_ref()
+    }
+
+    pub fn into_transcript(self) -> Option<Transcript> {
+        self.transcript
+    }
+
+    // A copy of the battle counting mana, spells and turns from now
+    pub(crate) fn restart(&self) -> Self {
+        Self {
+            mana_spent: 0,
+            cast: Vec::new(),
+            turns: 0,
+            transcript: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn spellbook(&self) -> &'a Spellbook {
+        self.spellbook
+    }
+
+    pub fn winner(&self) -> Option<Side> {
+        if !self.boss.is_alive() {
+            Some(Side::Player)
+        } else if !self.player.is_alive() {
+            Some(Side::Boss)
+        } else {
+            None
+        }
+    }
+
+    // Armour including any from active effects
+    pub fn player_armour(&self) -> u64 {
+        self.player.armour
+            + self
+                .player
+                .effects
+                .keys()
+                .map(|idx| self.spellbook[idx].effect.armour)
+                .sum::<u64>()
+    }
+
+    // Whether the spell can be cast, once the player's turn has started
+    pub fn can_cast(&self, idx: usize) -> Result<(), CastError> {
+        if self.winner().is_some() {
+            Err(CastError::Finished)
+        } else if self.player.mana < self.spellbook[idx].cost {
+            Err(CastError::Unaffordable)
+        } else if self.player.effects.is_active(idx) {
+            Err(CastError::Active)
+        } else {
+            Ok(())
+        }
+    }
+
+    // The player's turn casting the spell, then the boss's, stopping as soon as either
+    // side wins. Nothing happens if the spell can't be cast.
+    pub fn round(&mut self, idx: usize) -> Result<(), CastError> {
+        if self.winner().is_some() {
+            return Err(CastError::Finished);
+        }
+        let mut after = self.clone();
+        after.play_round(idx)?;
+        *self = after;
+        Ok(())
+    }
+
+    // As round, but leaving the battle part way through the player's turn if the spell
+    // can't be cast
+    pub(crate) fn play_round(&mut self, idx: usize) -> Result<(), CastError> {
+        self.start_turn(Side::Player);
+        if self.winner().is_none() {
+            self.can_cast(idx)?;
+            self.cast(idx);
+            if self.winner().is_none() {
+                self.start_turn(Side::Boss);
+                if self.winner().is_none() {
+                    self.attack();
+                }
+            }
+        }
+        let winner = self.winner();
+        if let Some(transcript) = &mut self.transcript {
+            transcript.winner = winner;
+        }
+        Ok(())
+    }
+
+    // The hard difficulty penalty and active effects
+    fn start_turn(&mut self, side: Side) {
+        self.turns += 1;
+        let armour = self.player_armour();
+        if let Some(transcript) = &mut self.transcript {
+            transcript.turns.push(Turn {
+                side,
+                player_hit_points: self.player.hit_points,
+                player_armour: armour,
+                player_mana: self.player.mana,
+                boss_hit_points: self.boss.hit_points,
+                events: Vec::new(),
+            });
+        }
+        if side == Side::Player && self.hard_difficulty {
+            self.player.hit_points -= 1;
+            self.say(|| "Player loses 1 hit point.".to_owned());
+            if self.player.hit_points == 0 {
+                self.say(|| "This kills the player, and the boss wins.".to_owned());
+                return;
+            }
+        }
+        self.apply_effects();
+    }
+
+    fn apply_effects(&mut self) {
+        let spellbook = self.spellbook;
+        for (idx, turns) in self.player.effects.tick() {
+            let spell = &spellbook[idx];
+            spell.effect.apply(&mut self.player, &mut self.boss);
+            if let Some(transcript) = &mut self.transcript {
+                let events = &mut transcript.turns.last_mut().unwrap().events;
+                let mut doing = Vec::new();
+                if spell.effect.damage > 0 {
+                    doing.push(format!("deals {} damage", spell.effect.damage));
+                }
+                if spell.effect.heal > 0 {
+                    doing.push(format!("heals {}", hit_points(spell.effect.heal)));
+                }
+                if spell.effect.mana > 0 {
+                    doing.push(format!("provides {} mana", spell.effect.mana));
+                }
+                let line = match (doing.is_empty(), self.boss.hit_points) {
+                    (false, 0) => format!(
+                        "{} {}. This kills the boss, and the player wins.",
+                        spell.name,
+                        doing.join(" and ")
+                    ),
+                    (false, _) => format!(
+                        "{} {}; its timer is now {turns}.",
+                        spell.name,
+                        doing.join(" and ")
+                    ),
+                    (true, _) => format!("{}'s timer is now {turns}.", spell.name),
+                };
+                events.push(line);
+                if turns == 0 && self.boss.hit_points > 0 {
+                    events.push(match spell.effect.armour {
+                        0 => format!("{} wears off.", spell.name),
+                        armour => {
+                            format!("{} wears off, decreasing armor by {armour}.", spell.name)
+                        }
+                    });
+                }
+            }
+            if self.boss.hit_points == 0 {
+                break;
+            }
+        }
+    }
+
+    fn cast(&mut self, idx: usize) {
+        let spell = &self.spellbook[idx];
+        self.player.mana -= spell.cost;
+        self.mana_spent += spell.cost;
+        self.cast.push(idx);
+        let mut immediate = spell.immediate;
+        if immediate.damage > 0 {
+            immediate.damage += self.player.damage;
+        }
+        immediate.apply(&mut self.player, &mut self.boss);
+        self.player.armour += spell.immediate.armour;
+        self.player.effects.start(idx, spell.duration);
+        if let Some(transcript) = &mut self.transcript {
+            transcript.mana_spent = self.mana_spent;
+            let mut doing = Vec::new();
+            if immediate.damage > 0 {
+                doing.push(format!("dealing {} damage", immediate.damage));
+            }
+            if spell.immediate.heal > 0 {
+                doing.push(format!("healing {}", hit_points(spell.immediate.heal)));
+            }
+            if spell.immediate.mana > 0 {
+                doing.push(format!("providing {} mana", spell.immediate.mana));
+            }
+            let armour = spell.immediate.armour + spell.effect.armour;
+            if armour > 0 {
+                doing.push(format!("increasing armor by {armour}"));
+            }
+            let mut line = format!("Player casts {}", spell.name);
+            for (n, part) in doing.iter().enumerate() {
+                line += if n == 0 { ", " } else { ", and " };
+                line += part;
+            }
+            line += ".";
+            if self.boss.hit_points == 0 {
+                line += " This kills the boss, and the player wins.";
+            }
+            transcript.turns.last_mut().unwrap().events.push(line);
+        }
+    }
+
+    fn attack(&mut self) {
+        let armour = self.player_armour();
+        let attack = self.boss.damage;
+        let damage = combat::damage(attack, armour);
+        self.player.hit_points = self.player.hit_points.saturating_sub(damage);
+        self.say(|| match armour {
+            0 => format!("Boss attacks for {damage} damage."),
+            _ => format!("Boss attacks for {attack} - {armour} = {damage} damage!"),
+        });
+        if self.player.hit_points == 0 {
+            self.say(|| "This kills the player, and the boss wins.".to_owned());
+        }
+    }
+
+    fn say(&mut self, line: impl FnOnce() -> String) {
+        if let Some(transcript) = &mut self.transcript {
+            transcript.turns.last_mut().unwrap().events.push(line());
+        }
+    }
+}
+
+fn hit_points(n: u64) -> String {
+    match n {
+        1 => "1 hit point".to_owned(),
+        _ => format!("{n} hit points"),
+    }
+}
+
+impl Transcript {
+    pub fn to_json(&self) -> Value {
+        let turns = self
+            .turns
+            .iter()
+            .map(|turn| {
+                json!({
+                    "side": turn.side.to_string(),
+                    "player": {
+                        "hit_points": turn.player_hit_points,
+                        "armour": turn.player_armour,
+                        "mana": turn.player_mana,
+                    },
+                    "boss": { "hit_points": turn.boss_hit_points },
+                    "events": turn.events,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "winner": self.winner.map(|side| side.to_string()),
+            "mana_spent": self.mana_spent,
+            "turns": turns,
+        })
+    }
+}
+
+impl fmt::Display for Turn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.side {
+            Side::Player => writeln!(f, "-- Player turn --")?,
+            Side::Boss => writeln!(f, "-- Boss turn --")?,
+        }
+        writeln!(
+            f,
+            "- Player has {}, {} armor, {} mana",
+            hit_points(self.player_hit_points),
+            self.player_armour,
+            self.player_mana
+        )?;
+        writeln!(f, "- Boss has {}", hit_points(self.boss_hit_points))?;
+        for event in &self.events {
+            writeln!(f, "{event}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (n, turn) in self.turns.iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{turn}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::example;
+
+    fn start(spellbook: &Spellbook, boss_hit_points: u64) -> Battle<'_> {
+        example(spellbook, boss_hit_points).recording()
+    }
+
+    #[test]
+    fn first_example() {
+        let spellbook = Spellbook::standard();
+        let mut battle = start(&spellbook, 13);
+        for name in ["poison", "magic missile"] {
+            battle.round(spellbook.find(name).unwrap()).unwrap();
+        }
+        let transcript = battle.into_transcript().unwrap();
+        assert_eq!(
+            "\
+-- Player turn --
+- Player has 10 hit points, 0 armor, 250 mana
+- Boss has 13 hit points
+Player casts Poison.
+
+-- Boss turn --
+- Player has 10 hit points, 0 armor, 77 mana
+- Boss has 13 hit points
+Poison deals 3 damage; its timer is now 5.
+Boss attacks for 8 damage.
+
+-- Player turn --
+- Player has 2 hit points, 0 armor, 77 mana
+- Boss has 10 hit points
+Poison deals 3 damage; its timer is now 4.
+Player casts Magic Missile, dealing 4 damage.
+
+-- Boss turn --
+- Player has 2 hit points, 0 armor, 24 mana
+- Boss has 3 hit points
+Poison deals 3 damage. This kills the boss, and the player wins.
+",
+            transcript.to_string()
+        );
+        assert_eq!(Some(Side::Player), transcript.winner);
+        let json = transcript.to_json();
+        assert_eq!(226, json["mana_spent"]);
+        assert_eq!(24, json["turns"][3]["player"]["mana"]);
+    }
+
+    #[test]
+    fn second_example() {
+        let spellbook = Spellbook::standard();
+        let mut battle = start(&spellbook, 14);
+        for name in ["recharge", "shield", "drain", "poison", "magic missile"] {
+            battle.round(spellbook.find(name).unwrap()).unwrap();
+        }
+        let transcript = battle.into_transcript().unwrap();
+        let text = transcript.to_string();
+        for line in [
+            "Player casts Shield, increasing armor by 7.",
+            "Boss attacks for 8 - 7 = 1 damage!",
+            "Player casts Drain, dealing 2 damage, and healing 2 hit points.",
+            "Recharge provides 101 mana; its timer is now 0.\nRecharge wears off.",
+            "Shield's timer is now 0.\nShield wears off, decreasing armor by 7.",
+            "- Player has 1 hit point, 0 armor, 114 mana",
+        ] {
+            assert!(text.contains(line), "{line}");
+        }
+        assert_eq!(10, transcript.turns.len());
+        assert_eq!(Some(Side::Player), transcript.winner);
+
+        // The same shield can't be cast while it is up, and nothing changes
+        let mut battle = start(&spellbook, 14);
+        let shield = spellbook.find("shield").unwrap();
+        battle.round(shield).unwrap();
+        let before = battle.player.clone();
+        assert_eq!(Err(CastError::Active), battle.round(shield));
+        assert_eq!(before, battle.player);
+    }
+
+    #[test]
+    fn equipment_and_spells() {
+        let spellbook = Spellbook::standard();
+        let armour = combat::Bonus {
+            damage: 0,
+            armour: 2,
+        };
+        let mut battle = start(&spellbook, 14).equipped(&armour);
+        battle.round(spellbook.find("shield").unwrap()).unwrap();
+        battle
+            .round(spellbook.find("magic missile").unwrap())
+            .unwrap();
+        let text = battle.transcript().unwrap().to_string();
+        // Worn armour adds to the shield's, and stays after it wears off
+        assert!(text.contains("- Player has 9 hit points, 9 armor, 84 mana"));
+        assert!(text.contains("Boss attacks for 8 - 9 = 1 damage!"));
+        assert_eq!((8, 2), (battle.player.hit_points, battle.player.armour));
+    }
+
+    #[test]
+    fn weapon_and_spells() {
+        let spellbook = Spellbook::standard();
+        let weapon = combat::Bonus {
+            damage: 3,
+            armour: 0,
+        };
+        let mut battle = start(&spellbook, 25).equipped(&weapon);
+        battle.boss.damage = 1;
+        battle.player.mana = 500;
+        for name in ["poison", "magic missile", "shield"] {
+            battle.round(spellbook.find(name).unwrap()).unwrap();
+        }
+        let text = battle.transcript().unwrap().to_string();
+        // Only spells which do damage when cast gain the weapon's damage
+        assert!(text.contains("Poison deals 3 damage; its timer is now 5."));
+        assert!(text.contains("Player casts Magic Missile, dealing 7 damage."));
+        assert!(text.contains("Player casts Shield, increasing armor by 7."));
+        assert_eq!(25 - 7 - 5 * 3, battle.boss.hit_points);
+    }
+}
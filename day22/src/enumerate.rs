@@ -0,0 +1,148 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{Battle, Side};
+
+// Limits on the spell sequences to try
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub max_mana: u64,
+    pub max_rounds: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Wins {
+    // Distinct winning spell sequences
+    pub count: u64,
+    // How many wins cost each amount of mana
+    pub costs: BTreeMap<u64, u64>,
+    // The cheapest win taking each number of turns, counting both sides
+    pub by_turns: BTreeMap<usize, (u64, Vec<usize>)>,
+    // Sequences given up on at max_rounds with the battle still going
+    pub cut_off: u64,
+}
+
+impl Battle<'_> {
+    // Every way of winning from here within the bounds. Each winning sequence of spells
+    // is also passed to `each` with its cost and the turns it took.
+    pub fn enumerate_wins(
+        &self,
+        bounds: Bounds,
+        mut each: impl FnMut(&[usize], u64, usize),
+    ) -> Wins {
+        let mut wins = Wins::default();
+        enumerate(&self.restart(), bounds, &mut wins, &mut each);
+        wins
+    }
+}
+
+fn enumerate(
+    battle: &Battle,
+    bounds: Bounds,
+    wins: &mut Wins,
+    each: &mut impl FnMut(&[usize], u64, usize),
+) {
+    if battle.cast.len() == bounds.max_rounds {
+        wins.cut_off += 1;
+        return;
+    }
+    for spell in 0..battle.spellbook().len() {
+        // Effects may win the round before the spell is cast, so it is the mana actually
+        // spent that counts
+        let mut next = battle.clone();
+        if next.play_round(spell).is_err() || next.mana_spent > bounds.max_mana {
+            continue;
+        }
+        // The battle ended before the spell was cast, whichever it was
+        let uncast = next.cast.len() == battle.cast.len();
+        match next.winner() {
+            Some(Side::Player) => {
+                wins.count += 1;
+                *wins.costs.entry(next.mana_spent).or_default() += 1;
+                let cheapest = wins
+                    .by_turns
+                    .entry(next.turns)
+                    .or_insert_with(|| (next.mana_spent, next.cast.clone()));
+                if next.mana_spent < cheapest.0 {
+                    *cheapest = (next.mana_spent, next.cast.clone());
+                }
+                each(&next.cast, next.mana_spent, next.turns);
+            }
+            Some(Side::Boss) => {}
+            None => enumerate(&next, bounds, wins, each),
+        }
+        if uncast {
+            break;
+        }
+    }
+}
+
+impl fmt::Display for Wins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} winning sequences", self.count)?;
+        if self.cut_off > 0 {
+            writeln!(f, "{} sequences cut off unfinished", self.cut_off)?;
+        }
+        writeln!(f, "{:>8} {:>10}", "mana", "wins")?;
+        for (cost, count) in &self.costs {
+            writeln!(f, "{cost:>8} {count:>10}")?;
+        }
+        writeln!(f, "{:>8} {:>10}", "turns", "cheapest")?;
+        for (turns, (cost, _)) in &self.by_turns {
+            writeln!(f, "{turns:>8} {cost:>10}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::example, Spellbook};
+
+    #[test]
+    fn first_example() {
+        let spellbook = Spellbook::standard();
+        let battle = example(&spellbook, 13);
+        let bounds = Bounds {
+            max_mana: 250,
+            max_rounds: 10,
+        };
+        let mut seen = Vec::new();
+        let wins =
+            battle.enumerate_wins(bounds, |spells, cost, _| seen.push((spells.to_vec(), cost)));
+        let (poison, missile) = (spellbook.find("poison"), spellbook.find("magic missile"));
+        // Nothing else leaves the player alive with so little mana
+        assert_eq!(1, wins.count);
+        assert_eq!(
+            Some(&(226, vec![poison.unwrap(), missile.unwrap()])),
+            wins.by_turns.get(&4)
+        );
+        assert_eq!(1, seen.len());
+        assert_eq!(Some(&1), wins.costs.get(&226));
+
+        let bounds = Bounds {
+            max_mana: 250,
+            max_rounds: 1,
+        };
+        let wins = battle.enumerate_wins(bounds, |_, _, _| {});
+        assert_eq!(0, wins.count);
+        assert!(wins.cut_off > 0);
+    }
+
+    #[test]
+    fn win_on_budget() {
+        let spellbook = Spellbook::standard();
+        let battle = example(&spellbook, 6);
+        let bounds = Bounds {
+            max_mana: 173,
+            max_rounds: 10,
+        };
+        // Poison finishes the boss at the start of the next round, with nothing more cast
+        let mut seen = Vec::new();
+        let wins =
+            battle.enumerate_wins(bounds, |spells, cost, _| seen.push((spells.to_vec(), cost)));
+        let poison = spellbook.find("poison").unwrap();
+        assert!(seen.contains(&(vec![poison], 173)));
+        assert_eq!(Some(&1), wins.costs.get(&173));
+    }
+}
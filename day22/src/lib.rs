@@ -1,15 +1,19 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::sync::Mutex;
-// You need to bring the trait into scope to use it!
-use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
 
-use once_cell::sync::Lazy;
+pub use battle::{Battle, CastError, Transcript, Turn};
+pub use combat::{Bonus, Character, Effect, Equipment, Side};
+pub use enumerate::{Bounds, Wins};
+pub use play::Game;
+pub use search::SearchStats;
+pub use spell::{Spell, Spellbook};
 
-static GLOBAL_BEST_KNOWN: Lazy<Mutex<Option<u64>>> = Lazy::new(|| Mutex::new(None));
+mod battle;
+mod enumerate;
+mod play;
+mod search;
+mod spell;
 
 pub fn load(filename: &str) -> Result<Solution> {
     let file = File::open(filename)?;
@@ -25,6 +29,14 @@ pub fn load(filename: &str) -> Result<Solution> {
 #[derive(Debug)]
 pub struct Solution {
     boss: Character,
+    spellbook: Spellbook,
+    // Worn by the player as well as casting spells, its damage adding to that of spells
+    equipment: Bonus,
+    // The cheapest winning spells for each part
+    spells_part1: Option<Vec<usize>>,
+    spells_part2: Option<Vec<usize>>,
+    stats_part1: Option<SearchStats>,
+    stats_part2: Option<SearchStats>,
 
     answer_part1: Option<u64>,
     answer_part2: Option<u64>,
@@ -40,12 +52,30 @@ impl Solution {
     pub fn new() -> Self {
         Self {
             boss: Character::default(),
+            spellbook: Spellbook::standard(),
+            equipment: Bonus::default(),
+            spells_part1: None,
+            spells_part2: None,
+            stats_part1: None,
+            stats_part2: None,
 
             answer_part1: None,
             answer_part2: None,
         }
     }
 
+    pub fn spellbook(&self) -> &Spellbook {
+        &self.spellbook
+    }
+
+    pub fn set_spellbook(&mut self, spellbook: Spellbook) {
+        self.spellbook = spellbook;
+    }
+
+    pub fn equip(&mut self, item: &impl Equipment) {
+        self.equipment = self.equipment + item.bonus();
+    }
+
     pub fn analyse(&mut self) {
         self.answer_part1 = self.analyse_part1();
         log::info!("part1: {:?}", self.answer_part1);
@@ -60,311 +90,177 @@ impl Solution {
     pub fn answer_part2(&self) -> Option<u64> {
         self.answer_part2
     }
+
+    pub fn spells_part1(&self) -> Option<&[usize]> {
+        self.spells_part1.as_deref()
+    }
+
+    pub fn spells_part2(&self) -> Option<&[usize]> {
+        self.spells_part2.as_deref()
+    }
+
+    pub fn stats_part1(&self) -> Option<SearchStats> {
+        self.stats_part1
+    }
+
+    pub fn stats_part2(&self) -> Option<SearchStats> {
+        self.stats_part2
+    }
+
+    // A new battle against the boss
+    pub fn battle(&self, hard_difficulty: bool) -> Battle<'_> {
+        Battle::new(&self.spellbook, player(), self.boss.clone())
+            .equipped(&self.equipment)
+            .with_hard_difficulty(hard_difficulty)
+    }
+
+    // Every way of winning within the bounds, passing each to `each` with its cost and
+    // the turns it took
+    pub fn enumerate(
+        &self,
+        hard_difficulty: bool,
+        bounds: Bounds,
+        each: impl FnMut(&[usize], u64, usize),
+    ) -> Wins {
+        self.battle(hard_difficulty).enumerate_wins(bounds, each)
+    }
+
+    // Fight the boss casting the given spells, one each round, until someone wins.
+    pub fn replay(&self, hard_difficulty: bool, spells: &[usize]) -> Result<Transcript> {
+        let mut battle = self.battle(hard_difficulty).recording();
+        for (round, &spell) in spells.iter().enumerate() {
+            battle.round(spell).with_context(|| {
+                format!(
+                    "round {}: can't cast {}",
+                    round + 1,
+                    self.spellbook[spell].name
+                )
+            })?;
+        }
+        Ok(battle.into_transcript().unwrap())
+    }
 }
 
 impl Solution {
     fn update_boss(&mut self, update: &str) -> Result<()> {
-        let update = update.trim();
-        if update.is_empty() {
-            return Ok(());
-        }
-        let (attr, value) = update
-            .split_once(':')
-            .context("should have an attribute name")?;
-        let value = value.trim().parse()?;
-        log::info!("{attr} <- {value}");
-        match attr.trim().to_lowercase().as_str() {
-            "hit points" => self.boss.hit_points = value,
-            "damage" => self.boss.damage = value,
-            "armor" => self.boss.armour = value,
-            _ => unreachable!("unknown attribute"),
-        };
-        Ok(())
+        self.boss.update(update)
     }
 
     fn analyse_part1(&mut self) -> Option<u64> {
-        let player = Character {
-            hit_points: 50,
-            mana: 500,
-            hard_difficulty: false,
-            ..Character::default()
-        };
-        Self::fight(player, self.boss.clone())
+        let (best, stats) = Self::fight(self.battle(false));
+        self.stats_part1 = Some(stats);
+        let (cost, spells) = best?;
+        self.spells_part1 = Some(spells);
+        Some(cost)
     }
 
     fn analyse_part2(&mut self) -> Option<u64> {
-        let player = Character {
-            hit_points: 50,
-            mana: 500,
-            hard_difficulty: true,
-            ..Character::default()
-        };
-        Self::fight(player, self.boss.clone())
+        let (best, stats) = Self::fight(self.battle(true));
+        self.stats_part2 = Some(stats);
+        let (cost, spells) = best?;
+        self.spells_part2 = Some(spells);
+        Some(cost)
     }
 
-    fn fight(player: Character, boss: Character) -> Option<u64> {
-        {
-            let mut best_known = GLOBAL_BEST_KNOWN.lock().unwrap();
-            *best_known = Some(1400);
-        }
-        let mut best_cost = None;
-        for spell in Spell::iter() {
-            if spell == Spell::None {
-                continue;
-            }
-            if let Some(cost) = Self::fight_pass(player.clone(), boss.clone(), spell, 0) {
-                best_cost = match best_cost {
-                    Some(bc) if bc < cost => best_cost,
-                    _ => Some(cost),
-                }
-            }
-        }
-        best_cost
+    // The least mana needed to win and the spells to cast
+    fn fight(battle: Battle) -> (Option<(u64, Vec<usize>)>, SearchStats) {
+        let (best, stats) = battle.cheapest_win();
+        log::info!("search: {stats}");
+        (best, stats)
     }
+}
 
-    fn fight_pass(
-        mut player: Character,
-        mut boss: Character,
-        mut spell: Spell,
-        mana_used: u64,
-    ) -> Option<u64> {
-        {
-            let best_known = GLOBAL_BEST_KNOWN.lock().unwrap();
-            if let Some(best) = *best_known {
-                if mana_used > best {
-                    log::info!(
-                        "Abort, already have a better solution ({}): {} {:?}",
-                        best,
-                        player.hard_difficulty,
-                        player.cast_spells
-                    );
-                    return None;
-                }
-            }
-        }
-        // 1. Player turn
-        log::debug!("-- Player turn --");
-        log::debug!(
-            "- Player has {} hit point(s), {} armour, {} mana",
-            player.hit_points,
-            player.armour,
-            player.mana
-        );
-        log::debug!("- Boss has {} hit point(s)", boss.hit_points);
-        // 1.0 Difficulty
-        if player.hard_difficulty {
-            if player.hit_points <= 1 {
-                log::debug!("Difficulty penalty, player dies");
-                return None;
-            }
-            player.hit_points -= 1;
-        }
-        // 1.1 Active spell effects
-        player.apply_spells(&mut boss);
-        if boss.hit_points == 0 {
-            log::debug!("Boss dead");
-            // Boss dies, no mana used this pass
-            return Some(mana_used);
-        }
-        // 1.2 Player cast spell
-        if player.mana < spell.cost() {
-            log::debug!("Can't cast {:?}, insufficient mana.", spell);
-            spell = Spell::None;
-        }
-        if let Some(turns) = player.spells.get(&spell) {
-            if *turns > 0 {
-                log::debug!("Can't cast {:?}, previous cast still active.", spell);
-                return None;
-            }
-        }
-        let cost = spell.cost();
-        player.mana -= cost;
-        match spell {
-            Spell::None => {
-                log::debug!("Player waits.");
-            }
-            Spell::MagicMissile => {
-                let damage = 4;
-                if boss.hit_points <= damage {
-                    // Boss dies
-                    log::debug!(
-                        "{:?} deals {} damage. This kills the boss, and the player wins.",
-                        spell,
-                        damage
-                    );
-                    return Some(mana_used + spell.cost());
-                }
-                boss.hit_points -= damage;
-                log::debug!("Player casts {:?}, dealing {} damage.", spell, damage);
-            }
-            Spell::Drain => {
-                let drain = 2;
-                player.hit_points += drain;
-                if boss.hit_points <= drain {
-                    // Boss dies
-                    log::debug!("Player casts Drain, dealing {} damage, and healing {} hit points. This kills the boss, and the player wins.", drain, drain);
-                    return Some(mana_used + spell.cost());
-                }
-                boss.hit_points -= drain;
-                log::debug!(
-                    "Player casts Drain, dealing {} damage, and healing {} hit points.",
-                    drain,
-                    drain
-                );
-            }
-            Spell::Shield => {
-                player.spells.insert(spell, 6);
-                player.armour += 7;
-                log::debug!("Player casts Shield, increasing armor by 7.");
-            }
-            Spell::Poison => {
-                player.spells.insert(spell, 6);
-                log::debug!("Player casts Poison.");
-            }
-            Spell::Recharge => {
-                player.spells.insert(spell, 5);
-                log::debug!("Player casts Recharge.");
-            }
-        };
-        player.cast_spells.push(spell);
-        if player.armour > 7 {
-            log::debug!("player: {:?}", player);
-            panic!("ffs");
-        }
-        // 2. Boss turn
-        log::debug!("-- Boss turn --");
-        log::debug!(
-            "- Player has {} hit point(s), {} armour, {} mana",
-            player.hit_points,
-            player.armour,
-            player.mana
-        );
-        log::debug!("- Boss has {} hit point(s)", boss.hit_points);
-        // 2.1 Active spell effects
-        player.apply_spells(&mut boss);
-        if boss.hit_points == 0 {
-            // Boss dies
-            return Some(mana_used + cost);
-        }
-        // 2.2 Boss attack
-        let damage = if boss.damage <= player.armour {
-            1
-        } else {
-            boss.damage - player.armour
-        };
-
-        log::debug!(
-            "Boss attacks for {} - {} = {} damage!",
-            boss.damage,
-            player.armour,
-            damage
-        );
-        if player.hit_points <= damage {
-            log::debug!("Player dead.");
-            return None;
-        }
-        player.hit_points -= damage;
-        let mut best_cost = None;
-        for spell in Spell::iter() {
-            if spell == Spell::None {
-                continue;
-            }
-            if let Some(cost) =
-                Self::fight_pass(player.clone(), boss.clone(), spell, mana_used + cost)
-            {
-                log::info!("Defeated boss, spending {} mana", cost);
-                best_cost = match best_cost {
-                    Some(bc) if bc < cost => best_cost,
-                    _ => Some(cost),
-                }
-            }
-        }
-        if let Some(this_best) = best_cost {
-            let mut best_known = GLOBAL_BEST_KNOWN.lock().unwrap();
-            *best_known = match *best_known {
-                Some(cost) if cost < this_best => Some(cost),
-                _ => Some(this_best),
-            };
-        }
-
-        best_cost
+fn player() -> Character {
+    Character {
+        hit_points: 50,
+        mana: 500,
+        ..Character::default()
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
-struct Character {
-    hit_points: u64,
-    damage: u64,
-    armour: u64,
-    mana: u64,
-    spells: HashMap<Spell, u64>,
-    cast_spells: Vec<Spell>,
-    hard_difficulty: bool,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Character {
-    fn apply_spells(&mut self, other: &mut Character) {
-        for (active_spell, turns) in self.spells.clone() {
-            if turns == 0 {
-                continue;
-            }
-            self.spells
-                .entry(active_spell)
-                .and_modify(|turns| *turns -= 1);
-            active_spell.apply(self, other, turns - 1);
-        }
+    // The player from the puzzle's examples, with 10 hit points and 250 mana, against a
+    // boss doing 8 damage
+    pub(crate) fn example(spellbook: &Spellbook, boss_hit_points: u64) -> Battle<'_> {
+        let player = Character {
+            hit_points: 10,
+            mana: 250,
+            ..Character::default()
+        };
+        let boss = Character {
+            hit_points: boss_hit_points,
+            damage: 8,
+            ..Character::default()
+        };
+        Battle::new(spellbook, player, boss)
     }
-}
 
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, EnumIter)]
-enum Spell {
-    None,
-    MagicMissile,
-    Drain,
-    Shield,
-    Poison,
-    Recharge,
-}
+    #[test]
+    fn fights() -> Result<()> {
+        let standard = Spellbook::standard();
+        let (poison, missile) = (standard.find("poison"), standard.find("magic missile"));
+        let (best, _) = Solution::fight(example(&standard, 13));
+        assert_eq!(
+            Some((173 + 53, vec![poison.unwrap(), missile.unwrap()])),
+            best
+        );
 
-impl Spell {
-    fn cost(&self) -> u64 {
-        match self {
-            Self::None => 0,
-            Self::MagicMissile => 53,
-            Self::Drain => 73,
-            Self::Shield => 113,
-            Self::Poison => 173,
-            Self::Recharge => 229,
-        }
+        let missiles = Spellbook::from_json(
+            r#"[{"name": "Missile", "cost": 10, "immediate": {"damage": 4}}]"#,
+        )?;
+        let (best, _) = Solution::fight(example(&missiles, 13));
+        assert_eq!(None, best);
+        let mut battle = example(&missiles, 13);
+        battle.boss.damage = 1;
+        let (best, _) = Solution::fight(battle);
+        assert_eq!(Some((40, vec![0; 4])), best);
+        Ok(())
     }
 
-    fn apply(&self, player: &mut Character, boss: &mut Character, turns: u64) {
-        match self {
-            Spell::Shield => {
-                log::debug!("Shield's timer is now {}.", turns);
-                if turns == 0 {
-                    log::debug!("Shield wears off, decreasing armor by 7.");
-                    player.armour -= 7;
-                }
-            }
-            Spell::Poison => {
-                let damage = 3;
-                if boss.hit_points <= damage {
-                    boss.hit_points = 0;
-                } else {
-                    boss.hit_points -= 3;
-                }
-                log::debug!(
-                    "Poison deals {} damage; its timer is now {}.",
-                    damage,
-                    turns
-                );
-            }
-            Spell::Recharge => {
-                player.mana += 101;
-                log::debug!("Recharge provides 101 mana; its timer is now {}.", turns);
-            }
-            _ => unreachable!(),
-        }
+    #[test]
+    fn equipped() {
+        let mut solution = Solution::new();
+        solution.boss = Character {
+            hit_points: 58,
+            damage: 9,
+            ..Character::default()
+        };
+        let bare = solution.analyse_part1().unwrap();
+        let armour = Bonus {
+            damage: 0,
+            armour: 8,
+        };
+        solution.equip(&armour);
+        let equipped = solution.analyse_part1().unwrap();
+        // With the boss doing 1 damage, shields are no longer worth the mana
+        assert!(equipped < bare);
+        let shield = solution.spellbook().find("shield").unwrap();
+        assert!(!solution.spells_part1().unwrap().contains(&shield));
+        let transcript = solution
+            .replay(false, solution.spells_part1().unwrap())
+            .unwrap();
+        assert_eq!(Some(Side::Player), transcript.winner);
+        assert_eq!(8, transcript.turns[0].player_armour);
+    }
+
+    // Well beyond the reach of a search capped at 1400 mana
+    #[test]
+    fn expensive() {
+        let mut solution = Solution::new();
+        solution.boss = Character {
+            hit_points: 90,
+            damage: 8,
+            ..Character::default()
+        };
+        let cost = solution.analyse_part1().unwrap();
+        assert!(cost > 1400);
+        let transcript = solution
+            .replay(false, solution.spells_part1().unwrap())
+            .unwrap();
+        assert_eq!(Some(Side::Player), transcript.winner);
+        assert_eq!(cost, transcript.mana_spent);
     }
 }
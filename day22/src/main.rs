@@ -1,13 +1,29 @@
-use anyhow::Result;
-use day22::load;
+use anyhow::{Context, Result};
+use day22::{load, Bounds, Game, Solution, Spellbook};
 use env_logger::Env;
 use log::{error, info};
+use std::io::{BufRead, Write};
 use yansi::Paint;
 
+const SPELLS: &str = "input/day22.spells.json";
+
 fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
     let mut solution = load("input/day22.input")?;
+    // Spells for a variant of the game, if there is one
+    if std::path::Path::new(SPELLS).exists() {
+        solution.set_spellbook(Spellbook::load(SPELLS)?);
+        info!("spells from {SPELLS}");
+    }
+    match std::env::args().nth(1).as_deref() {
+        Some("enumerate") => return enumerate(&solution),
+        Some("play") => {
+            let hard_difficulty = std::env::args().nth(2).as_deref() == Some("hard");
+            return play(Game::new(solution.battle(hard_difficulty)));
+        }
+        _ => {}
+    }
     info!(
         "{}{}: {:?}",
         Paint::masked("🎄 "),
@@ -33,5 +49,81 @@ fn main() -> Result<()> {
         _ => error!("{}No answer to part2", Paint::masked("🎅 ")),
     }
 
+    // Show how the answers were won, as text or JSON
+    if let Some(format @ ("replay" | "json")) = std::env::args().nth(1).as_deref() {
+        let parts = [
+            (false, solution.spells_part1()),
+            (true, solution.spells_part2()),
+        ];
+        for (hard_difficulty, spells) in parts {
+            let Some(spells) = spells else {
+                continue;
+            };
+            let transcript = solution.replay(hard_difficulty, spells)?;
+            match format {
+                "json" => println!("{}", transcript.to_json()),
+                _ => println!("{transcript}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// enumerate <max mana> <max rounds> [file to write the winning sequences to]
+fn enumerate(solution: &Solution) -> Result<()> {
+    let args = std::env::args().skip(2).collect::<Vec<_>>();
+    let bounds = Bounds {
+        max_mana: args
+            .first()
+            .context("needs the most mana to spend")?
+            .parse()?,
+        max_rounds: args
+            .get(1)
+            .context("needs the most rounds to play")?
+            .parse()?,
+    };
+    let mut out = match args.get(2) {
+        Some(filename) => Some(std::io::BufWriter::new(std::fs::File::create(filename)?)),
+        None => None,
+    };
+    let spellbook = solution.spellbook();
+    for (hard_difficulty, name) in [(false, "normal"), (true, "hard")] {
+        let mut written = Ok(());
+        let wins = solution.enumerate(hard_difficulty, bounds, |spells, cost, turns| {
+            if let (Some(out), Ok(())) = (&mut out, &written) {
+                let spells = spells
+                    .iter()
+                    .map(|&idx| spellbook[idx].name.as_str())
+                    .collect::<Vec<_>>();
+                written = writeln!(out, "{name}\t{cost}\t{turns}\t{}", spells.join(", "));
+            }
+        });
+        written?;
+        println!("{name} difficulty:\n{wins}");
+    }
+    Ok(())
+}
+
+// play [hard]
+fn play(mut game: Game) -> Result<()> {
+    print!("{}", game.execute("status")?);
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(day22) ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        if matches!(line.trim(), "q" | "quit") {
+            break;
+        }
+        match game.execute(&line) {
+            Ok(output) => print!("{output}"),
+            Err(e) => println!("{e}"),
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,170 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+
+use crate::{Battle, Side};
+
+const HELP: &str = "\
+cast <spell>      cast a spell by name or number, then the boss takes its turn
+<spell>           the same as cast
+spells            list the spells
+status            show both sides and the active effects
+hint              ask for the cheapest way to win from here
+help              show this text";
+
+// A battle played a round at a time from typed commands.
+pub struct Game<'a> {
+    battle: Battle<'a>,
+    // Turns of the transcript already shown
+    shown: usize,
+}
+
+impl<'a> Game<'a> {
+    pub fn new(battle: Battle<'a>) -> Self {
+        Self {
+            battle: battle.recording(),
+            shown: 0,
+        }
+    }
+
+    pub fn battle(&self) -> &Battle<'a> {
+        &self.battle
+    }
+
+    // Run one command line, returning what it has to say.
+    pub fn execute(&mut self, command: &str) -> Result<String> {
+        let command = command.trim();
+        let (verb, rest) = command.split_once(' ').unwrap_or((command, ""));
+        let mut out = String::new();
+        match verb {
+            "" => {}
+            "c" | "cast" => out += &self.cast(rest.trim())?,
+            "l" | "spells" => {
+                let spellbook = self.battle.spellbook();
+                for (idx, spell) in spellbook.spells().iter().enumerate() {
+                    write!(out, "{idx}: {} costs {} mana", spell.name, spell.cost).unwrap();
+                    if spell.duration > 0 {
+                        write!(out, " and lasts {} turns", spell.duration).unwrap();
+                    }
+                    writeln!(out).unwrap();
+                }
+            }
+            "s" | "status" => out += &self.status(),
+            "hint" => {
+                let spellbook = self.battle.spellbook();
+                let best = match self.battle.winner() {
+                    Some(_) => return Err(anyhow!("the battle is over")),
+                    None => self.battle.cheapest_win().0,
+                };
+                match best {
+                    Some((_, spells)) if spells.is_empty() => {
+                        writeln!(out, "no spell needed, the boss falls to active effects")
+                    }
+                    Some((cost, spells)) => writeln!(
+                        out,
+                        "cast {}: the boss can be beaten for {cost} more mana",
+                        spellbook[spells[0]].name
+                    ),
+                    None => writeln!(out, "there is no way to win from here"),
+                }
+                .unwrap();
+            }
+            "h" | "help" => writeln!(out, "{HELP}").unwrap(),
+            _ => match self.battle.spellbook().find(command) {
+                Some(_) => out += &self.cast(command)?,
+                None => return Err(anyhow!("unknown command {verb}, try help")),
+            },
+        }
+        Ok(out)
+    }
+
+    fn cast(&mut self, name: &str) -> Result<String> {
+        let spellbook = self.battle.spellbook();
+        let idx = match name.parse::<usize>() {
+            Ok(idx) if idx < spellbook.len() => idx,
+            Ok(idx) => return Err(anyhow!("there is no spell {idx}")),
+            Err(_) => spellbook
+                .find(name)
+                .ok_or_else(|| anyhow!("there is no spell called {name}"))?,
+        };
+        self.battle
+            .round(idx)
+            .map_err(|e| anyhow!("can't cast {}: {e}", spellbook[idx].name))?;
+
+        let mut out = String::new();
+        let turns = &self.battle.transcript().unwrap().turns;
+        for turn in &turns[self.shown..] {
+            writeln!(out, "{turn}").unwrap();
+        }
+        self.shown = turns.len();
+        match self.battle.winner() {
+            Some(Side::Player) => writeln!(
+                out,
+                "You win, having spent {} mana.",
+                self.battle.mana_spent
+            ),
+            Some(Side::Boss) => writeln!(out, "You lose."),
+            None => write!(out, "{}", self.status()),
+        }
+        .unwrap();
+        Ok(out)
+    }
+
+    fn status(&self) -> String {
+        let (player, boss) = (&self.battle.player, &self.battle.boss);
+        let mut out = format!(
+            "Player: {} hit points, {} armor, {} mana\nBoss: {} hit points\n",
+            player.hit_points,
+            self.battle.player_armour(),
+            player.mana,
+            boss.hit_points
+        );
+        let spellbook = self.battle.spellbook();
+        for (idx, turns) in player.effects.iter() {
+            writeln!(out, "{} has {turns} turns left", spellbook[idx].name).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::example, Spellbook};
+
+    #[test]
+    fn first_example() -> Result<()> {
+        let spellbook = Spellbook::standard();
+        let mut game = Game::new(example(&spellbook, 13));
+        assert_eq!(
+            "cast Poison: the boss can be beaten for 226 more mana\n",
+            game.execute("hint")?
+        );
+        let out = game.execute("cast poison")?;
+        assert!(out.starts_with("-- Player turn --\n"));
+        assert!(out.ends_with(
+            "Boss attacks for 8 damage.\n\n\
+             Player: 2 hit points, 0 armor, 77 mana\nBoss: 10 hit points\n\
+             Poison has 5 turns left\n"
+        ));
+        assert!(game.execute("poison").is_err());
+        assert!(game.execute("cast 9").is_err());
+        assert!(game.execute("Magic Missile")?.ends_with(
+            "This kills the boss, and the player wins.\n\nYou win, having spent 226 mana.\n"
+        ));
+        assert!(game.execute("hint").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn effects_finish_the_boss() -> Result<()> {
+        let spellbook = Spellbook::standard();
+        let mut game = Game::new(example(&spellbook, 6));
+        game.execute("poison")?;
+        assert_eq!(
+            "no spell needed, the boss falls to active effects\n",
+            game.execute("hint")?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, HashMap},
+    fmt,
+};
+
+use crate::{Battle, Character, Side};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    // States taken off the queue and tried with every spell
+    pub expanded: usize,
+    // Rounds played to reach new states or cheaper ways to known ones
+    pub generated: usize,
+    // Queue entries dropped because their state had been reached more cheaply
+    pub stale: usize,
+    // Distinct states seen
+    pub states: usize,
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} states, {} expanded, {} generated, {} stale",
+            self.states, self.expanded, self.generated, self.stale
+        )
+    }
+}
+
+// Everything about a battle that decides how it can go from here
+type State = (Character, u64);
+
+// How many distinct states cheapest_win looks at before giving up. Every spell costs
+// something, but a spellbook which can keep the player going without hurting the boss
+// would otherwise be searched for ever.
+pub const STATE_LIMIT: usize = 1_000_000;
+
+impl Battle<'_> {
+    // The least mana needed to win from here and the spells to cast, by Dijkstra's
+    // algorithm over the states of the battle. None if there is no way to win within
+    // STATE_LIMIT states.
+    pub fn cheapest_win(&self) -> (Option<(u64, Vec<usize>)>, SearchStats) {
+        self.cheapest_win_within(STATE_LIMIT)
+    }
+
+    pub fn cheapest_win_within(&self, limit: usize) -> (Option<(u64, Vec<usize>)>, SearchStats) {
+        let start = self.restart();
+        let mut stats = SearchStats::default();
+        let mut nodes = vec![start];
+        let mut best: HashMap<State, u64> = HashMap::new();
+        let mut queue = BinaryHeap::from([Reverse((0, 0))]);
+        while let Some(Reverse((cost, node))) = queue.pop() {
+            let battle = &nodes[node];
+            match battle.winner() {
+                Some(Side::Player) => {
+                    stats.states = best.len();
+                    return (Some((cost, battle.cast.clone())), stats);
+                }
+                Some(Side::Boss) => continue,
+                None => {}
+            }
+            if best.get(&battle.state()).is_some_and(|&known| known < cost) {
+                stats.stale += 1;
+                continue;
+            }
+            if best.len() >= limit {
+                log::warn!("giving up after {} states", best.len());
+                break;
+            }
+            stats.expanded += 1;
+            for spell in 0..battle.spellbook().len() {
+                let mut next = nodes[node].clone();
+                if next.play_round(spell).is_err() || next.winner() == Some(Side::Boss) {
+                    continue;
+                }
+                match best.entry(next.state()) {
+                    Entry::Occupied(entry) if *entry.get() <= next.mana_spent => continue,
+                    Entry::Occupied(mut entry) => {
+                        entry.insert(next.mana_spent);
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(next.mana_spent);
+                    }
+                }
+                stats.generated += 1;
+                queue.push(Reverse((next.mana_spent, nodes.len())));
+                nodes.push(next);
+            }
+        }
+        stats.states = best.len();
+        (None, stats)
+    }
+
+    fn state(&self) -> State {
+        (self.player.clone(), self.boss.hit_points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::example, Spellbook};
+
+    #[test]
+    fn from_mid_battle() {
+        let spellbook = Spellbook::standard();
+        let mut battle = example(&spellbook, 14);
+        let (best, stats) = battle.cheapest_win();
+        assert_eq!(Some(229 + 113 + 73 + 173 + 53), best.map(|(cost, _)| cost));
+        assert!(stats.expanded > 0 && stats.states >= stats.expanded);
+
+        // Having started well, the rest of the plan costs less
+        battle.round(spellbook.find("recharge").unwrap()).unwrap();
+        let (best, _) = battle.cheapest_win();
+        assert_eq!(Some(113 + 73 + 173 + 53), best.map(|(cost, _)| cost));
+        assert_eq!(229, battle.mana_spent);
+    }
+
+    #[test]
+    fn never_winning() -> anyhow::Result<()> {
+        // The player gains hit points and mana every round, so there are always new
+        // states, but the boss never gets hurt
+        let spellbook = Spellbook::from_json(
+            r#"[{"name": "Trance", "cost": 1, "immediate": {"heal": 20, "mana": 5}}]"#,
+        )?;
+        let battle = example(&spellbook, 13);
+        let (best, stats) = battle.cheapest_win_within(1000);
+        assert_eq!(None, best);
+        assert_eq!(1000, stats.states);
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Context, Result};
+use combat::Effect;
+use serde_json::Value;
+
+// The spells from the puzzle
+const STANDARD: &str = include_str!("../spells.json");
+
+// Changes made to the boss's hit points and the player's hit points, armour and mana,
+// from the fields of a JSON object
+fn parse_effect(value: &Value) -> Result<Effect> {
+    let mut effect = Effect::default();
+    let Some(fields) = value.as_object() else {
+        return match value {
+            Value::Null => Ok(effect),
+            _ => Err(anyhow!("effect should be an object")),
+        };
+    };
+    for (field, amount) in fields {
+        let amount = amount
+            .as_u64()
+            .with_context(|| format!("{field} should be a whole number"))?;
+        match field.as_str() {
+            "damage" => effect.damage = amount,
+            "heal" => effect.heal = amount,
+            "armour" => effect.armour = amount,
+            "mana" => effect.mana = amount,
+            _ => return Err(anyhow!("unknown effect {field}")),
+        }
+    }
+    Ok(effect)
+}
+
+// A spell has its immediate effect when cast. If it has a duration it then starts an
+// effect which applies at the start of each turn until its timer runs out; armour from
+// an effect only lasts while the effect is active, anything else accumulates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spell {
+    pub name: String,
+    pub cost: u64,
+    pub duration: u64,
+    pub immediate: Effect,
+    pub effect: Effect,
+}
+
+impl Spell {
+    fn parse(value: &Value) -> Result<Self> {
+        let name = value["name"]
+            .as_str()
+            .context("spell should have a name")?
+            .to_owned();
+        let number = |field: &str| match &value[field] {
+            Value::Null => Ok(0),
+            n => n
+                .as_u64()
+                .with_context(|| format!("{name}: {field} should be a whole number")),
+        };
+        let spell = Self {
+            cost: number("cost")?,
+            duration: number("duration")?,
+            immediate: parse_effect(&value["immediate"]).context(name.clone())?,
+            effect: parse_effect(&value["effect"]).context(name.clone())?,
+            name,
+        };
+        if spell.duration == 0 && !spell.effect.is_empty() {
+            return Err(anyhow!("{}: an effect needs a duration", spell.name));
+        }
+        // A free spell could be cast for ever without the search getting any dearer
+        if spell.cost == 0 {
+            return Err(anyhow!("{}: a spell should cost some mana", spell.name));
+        }
+        Ok(spell)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spellbook {
+    spells: Vec<Spell>,
+}
+
+impl Default for Spellbook {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl Spellbook {
+    pub fn standard() -> Self {
+        Self::from_json(STANDARD).unwrap()
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(filename)?;
+        Self::from_json(&text).with_context(|| filename.to_owned())
+    }
+
+    // A JSON list of spells, each with a name, cost, duration and immediate and
+    // per-turn effects. Anything left out is zero, except that every spell must have a
+    // cost.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(text)?;
+        let spells = value
+            .as_array()
+            .context("spells should be a list")?
+            .iter()
+            .map(Spell::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if spells.is_empty() {
+            return Err(anyhow!("there are no spells"));
+        }
+        Ok(Self { spells })
+    }
+
+    pub fn spells(&self) -> &[Spell] {
+        &self.spells
+    }
+
+    pub fn len(&self) -> usize {
+        self.spells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spells.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.spells
+            .iter()
+            .position(|spell| spell.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl std::ops::Index<usize> for Spellbook {
+    type Output = Spell;
+
+    fn index(&self, idx: usize) -> &Spell {
+        &self.spells[idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard() {
+        let spellbook = Spellbook::standard();
+        assert_eq!(5, spellbook.len());
+        let shield = &spellbook[spellbook.find("shield").unwrap()];
+        assert_eq!(113, shield.cost);
+        assert_eq!(6, shield.duration);
+        assert_eq!(7, shield.effect.armour);
+        assert!(shield.immediate.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Spellbook::from_json("[]").is_err());
+        assert!(Spellbook::from_json(r#"[{"cost": 1}]"#).is_err());
+        assert!(Spellbook::from_json(r#"[{"name": "a", "immediate": {"heal": 1}}]"#).is_err());
+        assert!(Spellbook::from_json(r#"[{"name": "a", "cost": -1}]"#).is_err());
+        assert!(Spellbook::from_json(r#"[{"name": "a", "effect": {"mana": 1}}]"#).is_err());
+        assert!(Spellbook::from_json(r#"[{"name": "a", "immediate": {"armor": 1}}]"#).is_err());
+    }
+}
@@ -1,45 +1,43 @@
 use anyhow::Result;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::str::FromStr;
 
-pub fn load(filename: &str) -> Result<Solution> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
+pub use vm::{
+    Debugger, Instruction, Kernels, Observer, Param, Profile, Profiler, Program, Tracer, Vm,
+    VmError,
+};
 
-    let mut solution = Solution::new();
-    for line in reader.lines().flatten() {
-        solution.add_instruction(Instruction::from_str(&line)?);
-    }
-    Ok(solution)
+pub mod vm;
+
+pub fn load(filename: &str) -> Result<Solution> {
+    let source = std::fs::read_to_string(filename)?;
+    Ok(Solution::new(Program::assemble(&source, &["a", "b"])?))
 }
 
 #[derive(Debug)]
 pub struct Solution {
-    instructions: Vec<Instruction>,
+    program: Program,
+    kernels: Kernels,
 
     answer_part1: Option<u64>,
     answer_part2: Option<u64>,
 }
 
-impl Default for Solution {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl Solution {
-    pub fn new() -> Self {
+    pub fn new(program: Program) -> Self {
         Self {
-            instructions: Vec::new(),
+            kernels: program.kernels(),
+            program,
 
             answer_part1: None,
             answer_part2: None,
         }
     }
 
-    pub fn add_instruction(&mut self, instruction: Instruction) {
-        self.instructions.push(instruction);
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn kernels(&self) -> &Kernels {
+        &self.kernels
     }
 
     pub fn analyse(&mut self) {
@@ -60,156 +58,49 @@ impl Solution {
 
 impl Solution {
     fn analyse_part1(&mut self) -> Option<u64> {
-        let (_, b) = self.run_program(0, 0, 0);
-        Some(b)
+        self.register_b(0)
     }
 
     fn analyse_part2(&mut self) -> Option<u64> {
-        let (_, b) = self.run_program(1, 0, 0);
-        Some(b)
+        self.register_b(1)
     }
 
-    fn run_program(&self, a: u64, b: u64, ip: usize) -> (u64, u64) {
-        let mut a = a;
-        let mut b = b;
-        let mut ip = ip;
-
-        while let Some(instruction) = self.instructions.get(ip) {
-            log::debug!("{ip}: {instruction:?} ({a} {b})");
-            ip += 1;
-            match instruction {
-                Instruction::Hlf(Param::A) => {
-                    a /= 2;
-                }
-                Instruction::Hlf(Param::B) => {
-                    b /= 2;
-                }
-                Instruction::Tpl(Param::A) => {
-                    a *= 3;
-                }
-                Instruction::Tpl(Param::B) => {
-                    b *= 3;
-                }
-                Instruction::Inc(Param::A) => {
-                    a += 1;
-                }
-                Instruction::Inc(Param::B) => {
-                    b += 1;
-                }
-                Instruction::Jmp(Param::Value(delta)) => {
-                    let new_ip = (ip as i64) + delta - 1;
-                    if new_ip < 0 || new_ip > self.instructions.len().try_into().unwrap() {
-                        ip = self.instructions.len();
-                    } else {
-                        ip = new_ip as usize;
-                    }
-                }
-                Instruction::Jie(Param::A, Param::Value(delta)) => {
-                    if a % 2 == 0 {
-                        let new_ip = (ip as i64) + delta - 1;
-                        if new_ip < 0 || new_ip > self.instructions.len().try_into().unwrap() {
-                            ip = self.instructions.len();
-                        } else {
-                            ip = new_ip as usize;
-                        }
-                    }
-                }
-                Instruction::Jie(Param::B, Param::Value(delta)) => {
-                    if b % 2 == 0 {
-                        let new_ip = (ip as i64) + delta - 1;
-                        if new_ip < 0 || new_ip > self.instructions.len().try_into().unwrap() {
-                            ip = self.instructions.len();
-                        } else {
-                            ip = new_ip as usize;
-                        }
-                    }
-                }
-                Instruction::Jio(Param::A, Param::Value(delta)) => {
-                    if a == 1 {
-                        let new_ip = (ip as i64) + delta - 1;
-                        if new_ip < 0 || new_ip > self.instructions.len().try_into().unwrap() {
-                            ip = self.instructions.len();
-                        } else {
-                            ip = new_ip as usize;
-                        }
-                    }
-                }
-                Instruction::Jio(Param::B, Param::Value(delta)) => {
-                    if b == 1 {
-                        let new_ip = (ip as i64) + delta - 1;
-                        if new_ip < 0 || new_ip > self.instructions.len().try_into().unwrap() {
-                            ip = self.instructions.len();
-                        } else {
-                            ip = new_ip as usize;
-                        }
-                    }
-                }
-                _ => unreachable!(),
+    fn register_b(&self, a: i64) -> Option<u64> {
+        match self.run_fast(a, 0) {
+            Ok((_, b)) => b.try_into().ok(),
+            Err(e) => {
+                log::error!("{e}");
+                None
             }
         }
-        (a, b)
     }
 }
 
-#[derive(Debug)]
-pub enum Instruction {
-    Hlf(Param),
-    Tpl(Param),
-    Inc(Param),
-    Jmp(Param),
-    Jie(Param, Param),
-    Jio(Param, Param),
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum Param {
-    Value(i64),
-    A,
-    B,
-}
+impl Solution {
+    // Execute every instruction, starting with registers a and b set, showing each one
+    // to the observer.
+    pub fn run_program(
+        &self,
+        a: i64,
+        b: i64,
+        observer: &mut impl Observer<i64>,
+    ) -> Result<(i64, i64), VmError> {
+        let mut vm = self.vm(a, b);
+        vm.run_observed(observer)?;
+        Ok((vm.registers[0], vm.registers[1]))
+    }
 
-impl FromStr for Instruction {
-    type Err = std::convert::Infallible;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use Instruction::*;
-
-        let s = s.trim().replace(',', " ");
-
-        let (op, params) =
-            s.split_whitespace()
-                .enumerate()
-                .fold(("", Vec::new()), |mut acc, (idx, v)| {
-                    match idx {
-                        0 => acc.0 = v,
-                        _ => acc.1.push(Param::from_str(v).unwrap()),
-                    }
-                    acc
-                });
-
-        let instruction = match op {
-            "hlf" => Hlf(params[0]),
-            "tpl" => Tpl(params[0]),
-            "inc" => Inc(params[0]),
-            "jmp" => Jmp(params[0]),
-            "jie" => Jie(params[0], params[1]),
-            "jio" => Jio(params[0], params[1]),
-            _ => unreachable!(),
-        };
-        Ok(instruction)
+    // As run_program, but skipping over loops and arithmetic which have a closed form.
+    pub fn run_fast(&self, a: i64, b: i64) -> Result<(i64, i64), VmError> {
+        let mut vm = self.vm(a, b);
+        vm.run_with(&self.kernels)?;
+        Ok((vm.registers[0], vm.registers[1]))
     }
-}
 
-impl FromStr for Param {
-    type Err = std::convert::Infallible;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use Param::*;
-        let param = match s {
-            "a" => A,
-            "b" => B,
-            _ => Value(s.parse().unwrap()),
-        };
-        Ok(param)
+    fn vm(&self, a: i64, b: i64) -> Vm<'_> {
+        let mut vm = Vm::new(&self.program);
+        vm.registers[0] = a;
+        vm.registers[1] = b;
+        vm
     }
 }
@@ -1,13 +1,52 @@
 use anyhow::Result;
-use day23::load;
+use day23::{load, Debugger, Profiler, Program, Tracer, Vm};
 use env_logger::Env;
 use log::{error, info};
+use std::io::{BufRead, Write};
 use yansi::Paint;
 
 fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
     let mut solution = load("input/day23.input")?;
+    match std::env::args().nth(1).as_deref() {
+        Some("disasm") => {
+            print!("{}", solution.program().disassemble());
+            return Ok(());
+        }
+        Some("debug") => return debug(solution.program()),
+        Some("flow") => {
+            let program = solution.program();
+            print!("{}", program.control_flow());
+            for (address, kernel) in solution.kernels().iter() {
+                println!(
+                    "kernel at {address}: {}",
+                    kernel.describe(program.registers())
+                );
+            }
+            return Ok(());
+        }
+        // Run with register a given by the next argument
+        Some("profile") => {
+            let mut profiler = Profiler::new(solution.program());
+            solution.run_program(start_a()?, 0, &mut profiler)?;
+            let profile = profiler.finish();
+            print!("{}", profile.annotate(solution.program()));
+            for block in profile.hottest().iter().take(3) {
+                info!("hot block {}..{}: {:?}", block.start, block.end, block.time);
+            }
+            return Ok(());
+        }
+        Some("trace") => {
+            let stdout = std::io::stdout();
+            let mut tracer =
+                Tracer::new(solution.program(), std::io::BufWriter::new(stdout.lock()));
+            solution.run_program(start_a()?, 0, &mut tracer)?;
+            tracer.finish()?;
+            return Ok(());
+        }
+        _ => {}
+    }
     info!(
         "{}{}: {:?}",
         Paint::masked("🎄 "),
@@ -35,3 +74,32 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+fn start_a() -> Result<i64> {
+    match std::env::args().nth(2) {
+        Some(a) => Ok(a.parse()?),
+        None => Ok(0),
+    }
+}
+
+fn debug(program: &Program) -> Result<()> {
+    let mut debugger = Debugger::new(Vm::new(program), 10000);
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(day23) ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        if matches!(line.trim(), "q" | "quit") {
+            break;
+        }
+        match debugger.execute(&line) {
+            Ok(output) => print!("{output}"),
+            Err(e) => println!("{e}"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{anyhow, Result};
+
+use super::{Instruction, Param, Program};
+
+impl Program {
+    // Assemble source with one instruction per line. A line may start with `label:`, and
+    // jump targets may be labels or offsets. `#` starts a comment. Registers are numbered
+    // in the order given, then in order of first use.
+    pub fn assemble(source: &str, registers: &[&str]) -> Result<Self> {
+        let mut program = Self {
+            registers: registers.iter().map(|r| r.to_string()).collect(),
+            ..Self::default()
+        };
+
+        let mut lines = Vec::new();
+        let mut labels = HashMap::new();
+        for (number, line) in source.lines().enumerate() {
+            let mut line = line.split('#').next().unwrap().trim();
+            if let Some((label, rest)) = line.split_once(':') {
+                let label = label.trim();
+                if !is_identifier(label) {
+                    return Err(anyhow!("line {}: bad label {label}", number + 1));
+                }
+                if labels.insert(label.to_owned(), lines.len()).is_some() {
+                    return Err(anyhow!("line {}: duplicate label {label}", number + 1));
+                }
+                program.labels.insert(lines.len(), label.to_owned());
+                line = rest.trim();
+            }
+            if !line.is_empty() {
+                lines.push((number + 1, line));
+            }
+        }
+
+        for (address, (number, line)) in lines.into_iter().enumerate() {
+            let instruction = program
+                .parse(address, line, &labels)
+                .map_err(|e| anyhow!("line {number}: {e}"))?;
+            program.instructions.push(instruction);
+        }
+        Ok(program)
+    }
+
+    pub fn disassemble(&self) -> String {
+        self.to_string()
+    }
+
+    // A single instruction as it would be disassembled.
+    pub fn listing(&self, address: usize) -> Option<String> {
+        (address < self.len()).then(|| self.text(address, &self.jump_labels()))
+    }
+
+    // Every instruction as it would be disassembled, by address.
+    pub fn listings(&self) -> Vec<String> {
+        let labels = self.jump_labels();
+        (0..self.len())
+            .map(|address| self.text(address, &labels))
+            .collect()
+    }
+
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(&address, _)| address)
+    }
+
+    fn text(&self, address: usize, labels: &HashMap<usize, String>) -> String {
+        let r = |x: usize| &self.registers[x];
+        let p = |param| self.param_name(param);
+        let t = |param| self.target_name(address, param, labels);
+        match self.instructions[address] {
+            Instruction::Hlf(x) => format!("hlf {}", r(x)),
+            Instruction::Tpl(x) => format!("tpl {}", r(x)),
+            Instruction::Inc(x) => format!("inc {}", r(x)),
+            Instruction::Dec(x) => format!("dec {}", r(x)),
+            Instruction::Cpy(s, x) => format!("cpy {} {}", p(s), r(x)),
+            Instruction::Add(s, x) => format!("add {} {}", p(s), r(x)),
+            Instruction::Mul(s, x) => format!("mul {} {}", p(s), r(x)),
+            Instruction::Jmp(d) => format!("jmp {}", t(d)),
+            Instruction::Jie(x, d) => format!("jie {}, {}", r(x), t(d)),
+            Instruction::Jio(x, d) => format!("jio {}, {}", r(x), t(d)),
+            Instruction::Jnz(c, d) => format!("jnz {} {}", p(c), t(d)),
+            Instruction::Out(s) => format!("out {}", p(s)),
+        }
+    }
+
+    fn parse(
+        &mut self,
+        address: usize,
+        line: &str,
+        labels: &HashMap<String, usize>,
+    ) -> Result<Instruction> {
+        let line = line.replace(',', " ");
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (op, args) = words.split_first().unwrap();
+        let arity = match *op {
+            "hlf" | "tpl" | "inc" | "dec" | "jmp" | "out" => 1,
+            "cpy" | "add" | "mul" | "jie" | "jio" | "jnz" => 2,
+            _ => return Err(anyhow!("unknown instruction {op}")),
+        };
+        if args.len() != arity {
+            return Err(anyhow!("{op} takes {arity} arguments"));
+        }
+
+        let instruction = match *op {
+            "hlf" => Instruction::Hlf(self.declare(args[0])?),
+            "tpl" => Instruction::Tpl(self.declare(args[0])?),
+            "inc" => Instruction::Inc(self.declare(args[0])?),
+            "dec" => Instruction::Dec(self.declare(args[0])?),
+            "out" => Instruction::Out(self.param(args[0])?),
+            "cpy" => Instruction::Cpy(self.param(args[0])?, self.declare(args[1])?),
+            "add" => Instruction::Add(self.param(args[0])?, self.declare(args[1])?),
+            "mul" => Instruction::Mul(self.param(args[0])?, self.declare(args[1])?),
+            "jmp" => Instruction::Jmp(self.target(address, args[0], labels)?),
+            "jie" => Instruction::Jie(
+                self.declare(args[0])?,
+                self.target(address, args[1], labels)?,
+            ),
+            "jio" => Instruction::Jio(
+                self.declare(args[0])?,
+                self.target(address, args[1], labels)?,
+            ),
+            "jnz" => Instruction::Jnz(self.param(args[0])?, self.target(address, args[1], labels)?),
+            _ => unreachable!(),
+        };
+        Ok(instruction)
+    }
+
+    fn declare(&mut self, name: &str) -> Result<usize> {
+        if !is_identifier(name) {
+            return Err(anyhow!("{name} is not a register"));
+        }
+        if let Some(r) = self.register(name) {
+            return Ok(r);
+        }
+        self.registers.push(name.to_owned());
+        Ok(self.registers.len() - 1)
+    }
+
+    fn param(&mut self, s: &str) -> Result<Param> {
+        match s.parse() {
+            Ok(v) => Ok(Param::Value(v)),
+            Err(_) => Ok(Param::Register(self.declare(s)?)),
+        }
+    }
+
+    fn target(&self, address: usize, s: &str, labels: &HashMap<String, usize>) -> Result<Param> {
+        // A register offset must name a register which is already known, so that a
+        // mistyped label is an error
+        if let Some(&target) = labels.get(s) {
+            return Ok(Param::Value(target as i64 - address as i64));
+        }
+        match (s.parse(), self.register(s)) {
+            (Ok(v), _) => Ok(Param::Value(v)),
+            (_, Some(r)) => Ok(Param::Register(r)),
+            _ => Err(anyhow!("unknown label {s}")),
+        }
+    }
+
+    // Label names for every address which a fixed jump lands on, including the end of the
+    // program; source names are kept and the rest are made up from the address.
+    fn jump_labels(&self) -> HashMap<usize, String> {
+        let mut labels = self
+            .labels
+            .iter()
+            .map(|(&address, label)| (address, label.to_owned()))
+            .collect::<HashMap<_, _>>();
+        for (address, instruction) in self.instructions.iter().enumerate() {
+            if let Some(target) = jump_target(address, instruction) {
+                if target <= self.len() {
+                    labels.entry(target).or_insert_with(|| format!("l{target}"));
+                }
+            }
+        }
+        labels
+    }
+
+    fn param_name(&self, param: Param) -> String {
+        match param {
+            Param::Value(v) => v.to_string(),
+            Param::Register(r) => self.registers[r].to_owned(),
+        }
+    }
+
+    fn target_name(&self, address: usize, param: Param, labels: &HashMap<usize, String>) -> String {
+        match param {
+            Param::Value(delta) => {
+                let target = address as i64 + delta;
+                match usize::try_from(target).ok().and_then(|t| labels.get(&t)) {
+                    Some(label) => label.to_owned(),
+                    None => format!("{delta:+}"),
+                }
+            }
+            Param::Register(_) => self.param_name(param),
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn jump_target(address: usize, instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Jmp(Param::Value(delta))
+        | Instruction::Jie(_, Param::Value(delta))
+        | Instruction::Jio(_, Param::Value(delta))
+        | Instruction::Jnz(_, Param::Value(delta)) => usize::try_from(address as i64 + delta).ok(),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels = self.jump_labels();
+        for address in 0..self.len() {
+            if let Some(label) = labels.get(&address) {
+                writeln!(f, "{label}:")?;
+            }
+            writeln!(f, "    {}", self.text(address, &labels))?;
+        }
+        if let Some(label) = labels.get(&self.len()) {
+            writeln!(f, "{label}:")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels() -> Result<()> {
+        let program = Program::assemble(
+            "
+            start: jio a, done   # stop at one
+                   inc b
+                   jie a, half
+                   tpl a
+                   inc a
+                   jmp start
+            half:  hlf a
+                   jmp start
+            done:
+            ",
+            &["a", "b"],
+        )?;
+        assert_eq!(
+            Instruction::Jio(0, Param::Value(8)),
+            program.instructions()[0]
+        );
+        assert_eq!(
+            Instruction::Jmp(Param::Value(-5)),
+            program.instructions()[5]
+        );
+        assert_eq!(
+            Instruction::Jmp(Param::Value(-7)),
+            program.instructions()[7]
+        );
+        assert!(Program::assemble("jmp nowhere", &[]).is_err());
+        assert!(Program::assemble("x: inc a\nx: inc a", &[]).is_err());
+        assert!(Program::assemble("inc 3", &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let program = Program::assemble(
+            "jio a, +4\ninc a\njie a, +2\ntpl a\nhlf a\njnz a -4\njmp +7\ncpy a c",
+            &["a", "b"],
+        )?;
+        let text = program.disassemble();
+        assert_eq!(
+            "    jio a, l4\n\
+             l1:\n    inc a\n    jie a, l4\n    tpl a\n\
+             l4:\n    hlf a\n    jnz a l1\n    jmp +7\n    cpy a c\n",
+            text
+        );
+        let mut reassembled = Program::assemble(&text, &["a", "b"])?;
+        reassembled.labels.clear();
+        assert_eq!(program, reassembled);
+        Ok(())
+    }
+}
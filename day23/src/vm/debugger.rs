@@ -0,0 +1,452 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use anyhow::{anyhow, Result};
+
+use super::{Program, Vm};
+
+const HELP: &str = "\
+step [n]          execute n instructions (default 1)
+back [n]          undo the last n instructions (default 1)
+continue [n]      run until a breakpoint, the program halts or n instructions have run
+break <target>    stop at an address, a label, or when an expression is non-zero
+delete <n>        remove breakpoint n
+watch <expr>      show an expression after every command
+unwatch <n>       remove watch n
+set <reg> <value> change a register, or ip
+print <expr>      evaluate an expression
+info              list breakpoints and watches
+list              show the instructions around ip
+help              show this text";
+
+// How many instructions either side of ip to list
+const CONTEXT: usize = 3;
+
+// How many instructions continue runs unless told otherwise
+const CONTINUE_STEPS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Value(i64),
+    Register(usize),
+    Ip,
+}
+
+// `<operand> [<operator> <operand>]` where an operand is a number, a register or ip.
+// Comparisons give 1 or 0.
+#[derive(Debug, Clone)]
+struct Expr {
+    text: String,
+    left: Operand,
+    right: Option<(Operator, Operand)>,
+}
+
+impl Expr {
+    fn parse(program: &Program, text: &str) -> Result<Self> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let operand = |s: &str| match (s.parse(), program.register(s)) {
+            (Ok(v), _) => Ok(Operand::Value(v)),
+            (_, Some(r)) => Ok(Operand::Register(r)),
+            _ if s == "ip" => Ok(Operand::Ip),
+            _ => Err(anyhow!("{s} is not a number or register")),
+        };
+        let right = match words[..] {
+            [_] => None,
+            [_, op, right] => {
+                let op = match op {
+                    "+" => Operator::Add,
+                    "-" => Operator::Sub,
+                    "*" => Operator::Mul,
+                    "/" => Operator::Div,
+                    "%" => Operator::Rem,
+                    "==" => Operator::Eq,
+                    "!=" => Operator::Ne,
+                    "<" => Operator::Lt,
+                    "<=" => Operator::Le,
+                    ">" => Operator::Gt,
+                    ">=" => Operator::Ge,
+                    _ => return Err(anyhow!("unknown operator {op}")),
+                };
+                Some((op, operand(right)?))
+            }
+            _ => return Err(anyhow!("cannot parse expression {text}")),
+        };
+        Ok(Self {
+            text: words.join(" "),
+            left: operand(words[0])?,
+            right,
+        })
+    }
+
+    // None on overflow or division by zero
+    fn eval(&self, vm: &Vm) -> Option<i64> {
+        let value = |operand| match operand {
+            Operand::Value(v) => v,
+            Operand::Register(r) => vm.registers[r],
+            Operand::Ip => vm.ip as i64,
+        };
+        let left = value(self.left);
+        let Some((op, right)) = self.right else {
+            return Some(left);
+        };
+        let right = value(right);
+        match op {
+            Operator::Add => left.checked_add(right),
+            Operator::Sub => left.checked_sub(right),
+            Operator::Mul => left.checked_mul(right),
+            Operator::Div => left.checked_div(right),
+            Operator::Rem => left.checked_rem(right),
+            Operator::Eq => Some((left == right).into()),
+            Operator::Ne => Some((left != right).into()),
+            Operator::Lt => Some((left < right).into()),
+            Operator::Le => Some((left <= right).into()),
+            Operator::Gt => Some((left > right).into()),
+            Operator::Ge => Some((left >= right).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Breakpoint {
+    Address(usize),
+    Condition(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    ip: usize,
+    registers: Vec<i64>,
+    output: usize,
+}
+
+pub struct Debugger<'a> {
+    vm: Vm<'a>,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Expr>,
+    // The state before each of the most recent steps, newest last
+    history: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl<'a> Debugger<'a> {
+    // A debugger which can step back through up to `capacity` instructions, or none if
+    // capacity is 0.
+    pub fn new(vm: Vm<'a>, capacity: usize) -> Self {
+        Self {
+            vm,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn vm(&self) -> &Vm<'a> {
+        &self.vm
+    }
+
+    // Run one command line, returning what it has to say.
+    pub fn execute(&mut self, command: &str) -> Result<String> {
+        let command = command.trim();
+        let (verb, rest) = command.split_once(' ').unwrap_or((command, ""));
+        let rest = rest.trim();
+        let mut out = String::new();
+        match verb {
+            "" => {}
+            "s" | "step" => {
+                for _ in 0..count(rest)? {
+                    if !self.step()? {
+                        break;
+                    }
+                }
+                out += &self.status();
+            }
+            "r" | "back" => {
+                let n = count(rest)?;
+                let undone = (0..n).take_while(|_| self.back()).count();
+                if undone < n {
+                    writeln!(out, "no more history").unwrap();
+                }
+                out += &self.status();
+            }
+            "c" | "continue" => {
+                let limit = match rest {
+                    "" => CONTINUE_STEPS,
+                    _ => rest.parse()?,
+                };
+                let mut steps = 0;
+                loop {
+                    if steps >= limit {
+                        writeln!(out, "stopped after {steps} steps").unwrap();
+                        break;
+                    }
+                    if !self.step()? {
+                        break;
+                    }
+                    steps += 1;
+                    if let Some(n) = self.hit() {
+                        writeln!(out, "breakpoint {n}").unwrap();
+                        break;
+                    }
+                }
+                out += &self.status();
+            }
+            "b" | "break" => {
+                let breakpoint = match (rest.parse(), self.vm.program().label(rest)) {
+                    (Ok(address), _) => Breakpoint::Address(address),
+                    (_, Some(address)) => Breakpoint::Address(address),
+                    _ => Breakpoint::Condition(Expr::parse(self.vm.program(), rest)?),
+                };
+                if let Breakpoint::Address(address) = breakpoint {
+                    if address >= self.vm.program().len() {
+                        return Err(anyhow!("there is no instruction at {address}"));
+                    }
+                }
+                self.breakpoints.push(breakpoint);
+                writeln!(out, "breakpoint {}", self.breakpoints.len() - 1).unwrap();
+            }
+            "d" | "delete" => {
+                remove(&mut self.breakpoints, rest)?;
+            }
+            "w" | "watch" => {
+                self.watches.push(Expr::parse(self.vm.program(), rest)?);
+                out += &self.watched();
+            }
+            "unwatch" => {
+                remove(&mut self.watches, rest)?;
+            }
+            "set" => {
+                let (name, value) = rest
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("set needs a register and a value"))?;
+                let value = value.trim().parse()?;
+                match name {
+                    "ip" => self.vm.ip = usize::try_from(value)?,
+                    _ => self.vm.set_register(name, value)?,
+                }
+                out += &self.status();
+            }
+            "p" | "print" => {
+                let expr = Expr::parse(self.vm.program(), rest)?;
+                writeln!(out, "{}", show(expr.eval(&self.vm))).unwrap();
+            }
+            "i" | "info" => {
+                for (n, breakpoint) in self.breakpoints.iter().enumerate() {
+                    match breakpoint {
+                        Breakpoint::Address(address) => writeln!(out, "breakpoint {n}: {address}"),
+                        Breakpoint::Condition(expr) => {
+                            writeln!(out, "breakpoint {n}: {}", expr.text)
+                        }
+                    }
+                    .unwrap();
+                }
+                out += &self.watched();
+            }
+            "l" | "list" => {
+                let program = self.vm.program();
+                let last = program.len().min(self.vm.ip + CONTEXT + 1);
+                for address in self.vm.ip.saturating_sub(CONTEXT)..last {
+                    let marker = if address == self.vm.ip { '>' } else { ' ' };
+                    writeln!(
+                        out,
+                        "{marker}{address:4}: {}",
+                        program.listing(address).unwrap()
+                    )
+                    .unwrap();
+                }
+            }
+            "h" | "help" => writeln!(out, "{HELP}").unwrap(),
+            _ => return Err(anyhow!("unknown command {verb}, try help")),
+        }
+        Ok(out)
+    }
+
+    fn step(&mut self) -> Result<bool> {
+        let snapshot = Snapshot {
+            ip: self.vm.ip,
+            registers: self.vm.registers.clone(),
+            output: self.vm.output.len(),
+        };
+        if !self.vm.step()? {
+            return Ok(false);
+        }
+        if self.capacity == 0 {
+            return Ok(true);
+        }
+        if self.history.len() >= self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(snapshot);
+        Ok(true)
+    }
+
+    fn back(&mut self) -> bool {
+        let Some(snapshot) = self.history.pop_back() else {
+            return false;
+        };
+        self.vm.ip = snapshot.ip;
+        self.vm.registers = snapshot.registers;
+        self.vm.output.truncate(snapshot.output);
+        self.vm.steps -= 1;
+        true
+    }
+
+    // The first breakpoint which applies to the current state
+    fn hit(&self) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| match breakpoint {
+                Breakpoint::Address(address) => self.vm.ip == *address,
+                Breakpoint::Condition(expr) => expr.eval(&self.vm).is_some_and(|v| v != 0),
+            })
+    }
+
+    fn status(&self) -> String {
+        let program = self.vm.program();
+        let registers = program
+            .registers()
+            .iter()
+            .zip(&self.vm.registers)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut out = match program.listing(self.vm.ip) {
+            Some(text) => format!("{}: {text}    {registers}\n", self.vm.ip),
+            None => format!("halted after {} steps    {registers}\n", self.vm.steps),
+        };
+        out += &self.watched();
+        out
+    }
+
+    fn watched(&self) -> String {
+        self.watches
+            .iter()
+            .map(|expr| format!("{} = {}\n", expr.text, show(expr.eval(&self.vm))))
+            .collect()
+    }
+}
+
+fn count(s: &str) -> Result<usize> {
+    match s {
+        "" => Ok(1),
+        _ => Ok(s.parse()?),
+    }
+}
+
+fn remove<T>(items: &mut Vec<T>, s: &str) -> Result<()> {
+    let n = s.parse::<usize>()?;
+    if n >= items.len() {
+        return Err(anyhow!("there is no {n}"));
+    }
+    items.remove(n);
+    Ok(())
+}
+
+fn show(value: Option<i64>) -> String {
+    value.map_or_else(|| "undefined".to_owned(), |v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program() -> Program {
+        Program::assemble(
+            "
+            start: jio a, done
+                   inc b
+                   jie a, half
+                   tpl a
+                   inc a
+                   jmp start
+            half:  hlf a
+                   jmp start
+            done:
+            ",
+            &["a", "b"],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn breakpoints() -> Result<()> {
+        let program = program();
+        let mut debugger = Debugger::new(Vm::new(&program), 100);
+        debugger.execute("set a 3")?;
+        assert_eq!("breakpoint 0\n", debugger.execute("break half")?);
+        assert_eq!(
+            "breakpoint 0\n6: hlf a    a=10 b=2\n",
+            debugger.execute("continue")?
+        );
+        debugger.execute("delete 0")?;
+        debugger.execute("break b == 5")?;
+        assert_eq!(
+            "breakpoint 0\n2: jie a, half    a=8 b=5\n",
+            debugger.execute("c")?
+        );
+        debugger.execute("delete 0")?;
+        assert_eq!("halted after 38 steps    a=1 b=7\n", debugger.execute("c")?);
+        assert!(debugger.execute("break 8").is_err());
+        assert!(debugger.execute("break done").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn history_and_watches() -> Result<()> {
+        let program = program();
+        let mut debugger = Debugger::new(Vm::new(&program), 2);
+        debugger.execute("set a 6")?;
+        assert_eq!("a % 2 = 0\n", debugger.execute("watch a % 2")?);
+        assert_eq!(
+            "6: hlf a    a=6 b=1\na % 2 = 0\n",
+            debugger.execute("step 3")?
+        );
+        assert_eq!(
+            "0: jio a, done    a=3 b=1\na % 2 = 1\n",
+            debugger.execute("s 2")?
+        );
+        assert_eq!(
+            "no more history\n6: hlf a    a=6 b=1\na % 2 = 0\n",
+            debugger.execute("back 3")?
+        );
+        assert_eq!(3, debugger.vm().steps);
+        assert_eq!("undefined\n", debugger.execute("print a / 0")?);
+        assert!(debugger.execute("frobnicate").is_err());
+        assert!(debugger.execute("set c 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn without_history() -> Result<()> {
+        let program = Program::assemble("start: inc a\njmp start", &["a"])?;
+        let mut debugger = Debugger::new(Vm::new(&program), 0);
+        assert_eq!(
+            "stopped after 1000 steps\n0: inc a    a=500\n",
+            debugger.execute("continue 1000")?
+        );
+        assert!(debugger.history.is_empty());
+        assert_eq!(
+            "stopped after 0 steps\n0: inc a    a=500\n",
+            debugger.execute("continue 0")?
+        );
+        assert_eq!(
+            "no more history\n0: inc a    a=500\n",
+            debugger.execute("back")?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use super::{Instruction, Param, Program};
+
+// A straight run of instructions which is only entered at `start`. Successors are
+// the addresses control can pass to next, with None for leaving the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<Option<usize>>,
+    // Ends in a jump through a register, whose target is unknown
+    pub indirect: bool,
+}
+
+// The blocks of a natural loop, entered at `head` and closed by a jump back from `tail`,
+// where every way into tail from the start of the program passes through head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub head: usize,
+    pub tail: usize,
+    pub blocks: BTreeSet<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlow {
+    pub blocks: Vec<Block>,
+    pub loops: Vec<Loop>,
+}
+
+impl ControlFlow {
+    pub fn block(&self, start: usize) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.start == start)
+    }
+}
+
+impl Program {
+    pub fn control_flow(&self) -> ControlFlow {
+        let len = self.len();
+        let mut leaders = BTreeSet::from([0]);
+        for (address, instruction) in self.instructions.iter().enumerate() {
+            if let Some((_, target)) = branch(address, instruction) {
+                leaders.insert(address + 1);
+                if let Some(target) = target.filter(|&t| t < len) {
+                    leaders.insert(target);
+                }
+            }
+        }
+        leaders.retain(|&address| address < len);
+
+        let starts = leaders.iter().copied().collect::<Vec<_>>();
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(idx, &start)| {
+                let end = starts.get(idx + 1).copied().unwrap_or(len);
+                let last = end - 1;
+                let inside = |address: usize| Some(address).filter(|&a| a < len);
+                let mut successors = Vec::new();
+                let mut indirect = false;
+                match branch(last, &self.instructions[last]) {
+                    Some((conditional, target)) => {
+                        if conditional {
+                            successors.push(inside(end));
+                        }
+                        match target {
+                            Some(target) => successors.push(inside(target)),
+                            None => indirect = true,
+                        }
+                    }
+                    None => successors.push(inside(end)),
+                }
+                successors.dedup();
+                Block {
+                    start,
+                    end,
+                    successors,
+                    indirect,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let dominators = dominators(&blocks);
+        let mut loops = Vec::new();
+        for (block, dominators) in blocks.iter().zip(&dominators) {
+            for &head in block.successors.iter().flatten() {
+                if dominators.as_ref().is_some_and(|d| d.contains(&head)) {
+                    loops.push(Loop {
+                        head,
+                        tail: block.start,
+                        blocks: natural_loop(&blocks, head, block.start),
+                    });
+                }
+            }
+        }
+        ControlFlow { blocks, loops }
+    }
+}
+
+// Whether an instruction can fall through, and where it jumps to if that is fixed. None
+// for instructions which are not jumps, or never jump. Targets before the start of the
+// program count as leaving it, as they do when run.
+fn branch(address: usize, instruction: &Instruction) -> Option<(bool, Option<usize>)> {
+    let target = |param: &Param| match param {
+        Param::Value(delta) => Some(usize::try_from(address as i64 + delta).unwrap_or(usize::MAX)),
+        Param::Register(_) => None,
+    };
+    match instruction {
+        Instruction::Jmp(p) => Some((false, target(p))),
+        Instruction::Jnz(Param::Value(0), _) => None,
+        Instruction::Jnz(Param::Value(_), p) => Some((false, target(p))),
+        Instruction::Jie(_, p) | Instruction::Jio(_, p) | Instruction::Jnz(_, p) => {
+            Some((true, target(p)))
+        }
+        _ => None,
+    }
+}
+
+// The starts of the blocks which every path from the first block to each block passes
+// through, including the block itself. None for blocks which can't be reached.
+fn dominators(blocks: &[Block]) -> Vec<Option<BTreeSet<usize>>> {
+    let index = |start: usize| blocks.iter().position(|block| block.start == start);
+    let mut reachable = vec![false; blocks.len()];
+    let mut pending = (0..blocks.len().min(1)).collect::<Vec<_>>();
+    while let Some(idx) = pending.pop() {
+        if std::mem::replace(&mut reachable[idx], true) {
+            continue;
+        }
+        pending.extend(
+            blocks[idx]
+                .successors
+                .iter()
+                .flatten()
+                .filter_map(|&s| index(s)),
+        );
+    }
+
+    let all = blocks
+        .iter()
+        .map(|block| block.start)
+        .collect::<BTreeSet<_>>();
+    let mut dominators = reachable
+        .iter()
+        .map(|&reachable| reachable.then(|| all.clone()))
+        .collect::<Vec<_>>();
+    if let Some(first) = dominators.first_mut() {
+        *first = Some(BTreeSet::from([0]));
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 1..blocks.len() {
+            if !reachable[idx] {
+                continue;
+            }
+            let start = blocks[idx].start;
+            let mut new = blocks
+                .iter()
+                .zip(&dominators)
+                .filter(|(block, _)| block.successors.contains(&Some(start)))
+                .filter_map(|(_, dominators)| dominators.as_ref())
+                .fold(None, |acc: Option<BTreeSet<usize>>, d| match acc {
+                    Some(acc) => Some(acc.intersection(d).copied().collect()),
+                    None => Some(d.clone()),
+                })
+                .unwrap_or_default();
+            new.insert(start);
+            if dominators[idx].as_ref() != Some(&new) {
+                dominators[idx] = Some(new);
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+// Every block which can reach tail without passing through head
+fn natural_loop(blocks: &[Block], head: usize, tail: usize) -> BTreeSet<usize> {
+    let mut body = BTreeSet::from([head, tail]);
+    let mut pending = vec![tail];
+    let mut seen = HashSet::from([head]);
+    while let Some(start) = pending.pop() {
+        if !seen.insert(start) {
+            continue;
+        }
+        for block in blocks {
+            if block.successors.contains(&Some(start)) {
+                body.insert(block.start);
+                pending.push(block.start);
+            }
+        }
+    }
+    body
+}
+
+impl fmt::Display for ControlFlow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for block in &self.blocks {
+            let successors = block
+                .successors
+                .iter()
+                .map(|s| s.map_or_else(|| "exit".to_owned(), |s| s.to_string()))
+                .chain(block.indirect.then(|| "?".to_owned()))
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "block {}..{} -> {}",
+                block.start,
+                block.end,
+                successors.join(", ")
+            )?;
+        }
+        for l in &self.loops {
+            let blocks = l.blocks.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+            writeln!(
+                f,
+                "loop at {} closed from {}: blocks {}",
+                l.head,
+                l.tail,
+                blocks.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hailstone() -> anyhow::Result<()> {
+        let program = Program::assemble(
+            "
+                   inc a
+            start: jio a, done
+                   inc b
+                   jie a, half
+                   tpl a
+                   inc a
+                   jmp start
+            half:  hlf a
+                   jmp start
+            done:
+            ",
+            &["a", "b"],
+        )?;
+        let flow = program.control_flow();
+        let blocks = flow
+            .blocks
+            .iter()
+            .map(|b| (b.start, b.end, b.successors.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, 1, vec![Some(1)]),
+                (1, 2, vec![Some(2), None]),
+                (2, 4, vec![Some(4), Some(7)]),
+                (4, 7, vec![Some(1)]),
+                (7, 9, vec![Some(1)]),
+            ],
+            blocks
+        );
+        assert_eq!(2, flow.loops.len());
+        assert_eq!(BTreeSet::from([1, 2, 4]), flow.loops[0].blocks);
+        assert_eq!(BTreeSet::from([1, 2, 7]), flow.loops[1].blocks);
+        Ok(())
+    }
+
+    #[test]
+    fn not_loops() -> anyhow::Result<()> {
+        // The jump back to first can be reached without passing first, and jnz 0 never
+        // jumps
+        let program = Program::assemble(
+            "
+                    jie a, second
+            first:  inc b
+            second: inc a
+                    jio a, first
+                    jnz 0, second
+            ",
+            &["a", "b"],
+        )?;
+        let flow = program.control_flow();
+        assert_eq!(
+            Some(&vec![Some(4), Some(1)]),
+            flow.block(2).map(|b| &b.successors)
+        );
+        assert_eq!(Some(&vec![None]), flow.block(4).map(|b| &b.successors));
+        assert!(flow.loops.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    time::Instant,
+};
+
+use super::{Instruction, Param, Program, Vm, VmError};
+
+// A closed form for running from an address to `exit`, giving the same registers and
+// step count as executing each instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kernel {
+    // Straight line arithmetic with constants: each register becomes mul * r + add
+    Affine {
+        maps: Vec<(usize, i64, i64)>,
+        exit: usize,
+    },
+    // A loop which decrements counter to zero, adding a fixed delta to other registers
+    // on each pass
+    Counted {
+        counter: usize,
+        deltas: Vec<(usize, i64)>,
+        body: u64,
+        exit: usize,
+    },
+    // Halves even x and takes odd x to 3x + 1 until x is 1, counting the moves in steps.
+    // An odd value takes odd instructions, which depends on where its jump goes.
+    Hailstone {
+        x: usize,
+        steps: usize,
+        odd: u64,
+        exit: usize,
+    },
+}
+
+// New register values, steps taken and where to continue from
+type Outcome = (Vec<(usize, i64)>, u64, usize);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Kernels(BTreeMap<usize, Kernel>);
+
+impl Kernels {
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Kernel)> {
+        self.0.iter().map(|(&address, kernel)| (address, kernel))
+    }
+
+    pub fn get(&self, address: usize) -> Option<&Kernel> {
+        self.0.get(&address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Program {
+    // Kernels for every loop and straight run of arithmetic that has one, by entry address.
+    pub fn kernels(&self) -> Kernels {
+        let flow = self.control_flow();
+        let mut kernels = BTreeMap::new();
+        for l in &flow.loops {
+            if let Some(kernel) = self.hailstone(l.head).or_else(|| self.counted(l.head)) {
+                kernels.insert(l.head, kernel);
+            }
+        }
+        for block in &flow.blocks {
+            if let Entry::Vacant(entry) = kernels.entry(block.start) {
+                if let Some(kernel) = self.affine(block.start, block.end) {
+                    entry.insert(kernel);
+                }
+            }
+        }
+        Kernels(kernels)
+    }
+
+    fn affine(&self, start: usize, end: usize) -> Option<Kernel> {
+        let mut maps: Vec<(usize, i64, i64)> = Vec::new();
+        let mut exit = start;
+        for instruction in &self.instructions[start..end] {
+            let (r, mul, add) = match *instruction {
+                Instruction::Inc(r) => (r, 1, 1),
+                Instruction::Dec(r) => (r, 1, -1),
+                Instruction::Tpl(r) => (r, 3, 0),
+                Instruction::Add(Param::Value(v), r) => (r, 1, v),
+                Instruction::Mul(Param::Value(v), r) => (r, v, 0),
+                Instruction::Cpy(Param::Value(v), r) => (r, 0, v),
+                _ => break,
+            };
+            let idx = match maps.iter().position(|m| m.0 == r) {
+                Some(idx) => idx,
+                None => {
+                    maps.push((r, 1, 0));
+                    maps.len() - 1
+                }
+            };
+            let (_, m, a) = maps[idx];
+            maps[idx] = (
+                r,
+                m.checked_mul(mul)?,
+                a.checked_mul(mul)?.checked_add(add)?,
+            );
+            exit += 1;
+        }
+        (exit - start > 1).then_some(Kernel::Affine { maps, exit })
+    }
+
+    // inc/dec/add by constants ending in `jnz counter` back to head, with the counter
+    // going down by exactly one each time round
+    fn counted(&self, head: usize) -> Option<Kernel> {
+        let jump = (head..self.len()).find(|&address| self.is_jump(address))?;
+        let Instruction::Jnz(Param::Register(counter), Param::Value(delta)) =
+            self.instructions[jump]
+        else {
+            return None;
+        };
+        if jump as i64 + delta != head as i64 {
+            return None;
+        }
+        let mut deltas: Vec<(usize, i64)> = Vec::new();
+        for instruction in &self.instructions[head..jump] {
+            let (r, v) = match *instruction {
+                Instruction::Inc(r) => (r, 1),
+                Instruction::Dec(r) => (r, -1),
+                Instruction::Add(Param::Value(v), r) => (r, v),
+                _ => return None,
+            };
+            match deltas.iter_mut().find(|d| d.0 == r) {
+                Some(d) => d.1 = d.1.checked_add(v)?,
+                None => deltas.push((r, v)),
+            }
+        }
+        let idx = deltas.iter().position(|d| d.0 == counter)?;
+        if deltas.remove(idx).1 != -1 {
+            return None;
+        }
+        Some(Kernel::Counted {
+            counter,
+            deltas,
+            body: (jump + 1 - head) as u64,
+            exit: jump + 1,
+        })
+    }
+
+    fn is_jump(&self, address: usize) -> bool {
+        matches!(
+            self.instructions[address],
+            Instruction::Jmp(_)
+                | Instruction::Jie(..)
+                | Instruction::Jio(..)
+                | Instruction::Jnz(..)
+        )
+    }
+
+    // The day 23 loop:
+    //   head: jio x, exit
+    //         inc steps
+    //         jie x, half
+    //         tpl x
+    //         inc x
+    //         jmp next        (or jmp head)
+    //   half: hlf x
+    //   next: jmp head
+    //   exit:
+    fn hailstone(&self, head: usize) -> Option<Kernel> {
+        use Instruction::*;
+        use Param::Value;
+
+        let code = self.instructions.get(head..head + 8)?;
+        let Jio(x, Value(8)) = code[0] else {
+            return None;
+        };
+        let Inc(steps) = code[1] else {
+            return None;
+        };
+        let expected = [
+            Jio(x, Value(8)),
+            Inc(steps),
+            Jie(x, Value(4)),
+            Tpl(x),
+            Inc(x),
+            code[5],
+            Hlf(x),
+            Jmp(Value(-7)),
+        ];
+        // Straight back to head, or by way of the jump after half
+        let odd = match code[5] {
+            Jmp(Value(-5)) => 6,
+            Jmp(Value(2)) => 7,
+            _ => return None,
+        };
+        (x != steps && code == expected).then_some(Kernel::Hailstone {
+            x,
+            steps,
+            odd,
+            exit: head + 8,
+        })
+    }
+}
+
+impl<'a> Vm<'a, i64> {
+    pub fn run_with(&mut self, kernels: &Kernels) -> Result<(), VmError> {
+        let started = Instant::now();
+        for round in 0.. {
+            if self.halted() {
+                break;
+            }
+            self.check_limits(started, round)?;
+            if !self.fast_forward(kernels) {
+                self.step()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Jump straight to the exit of the kernel at ip, if there is one which applies and
+    // its steps fit in the step limit.
+    pub fn fast_forward(&mut self, kernels: &Kernels) -> bool {
+        let Some(kernel) = kernels.get(self.ip) else {
+            return false;
+        };
+        let Some((updates, steps, exit)) = self.forward(kernel) else {
+            return false;
+        };
+        if self
+            .limits
+            .max_steps
+            .is_some_and(|max| self.steps.saturating_add(steps) > max)
+        {
+            return false;
+        }
+        log::debug!(
+            "{}: {} in {steps} steps",
+            self.ip,
+            kernel.describe(self.program.registers())
+        );
+        for (r, value) in updates {
+            self.registers[r] = value;
+        }
+        self.steps += steps;
+        self.ip = exit.min(self.program.len());
+        true
+    }
+
+    fn forward(&self, kernel: &Kernel) -> Option<Outcome> {
+        let r = &self.registers;
+        match kernel {
+            Kernel::Affine { maps, exit } => {
+                let updates = maps
+                    .iter()
+                    .map(|&(x, mul, add)| Some((x, r[x].checked_mul(mul)?.checked_add(add)?)))
+                    .collect::<Option<Vec<_>>>()?;
+                Some((updates, (exit - self.ip) as u64, *exit))
+            }
+            Kernel::Counted {
+                counter,
+                deltas,
+                body,
+                exit,
+            } => {
+                let n = r[*counter];
+                if n <= 0 {
+                    return None;
+                }
+                let mut updates = deltas
+                    .iter()
+                    .map(|&(x, delta)| Some((x, r[x].checked_add(delta.checked_mul(n)?)?)))
+                    .collect::<Option<Vec<_>>>()?;
+                updates.push((*counter, 0));
+                Some((updates, body.checked_mul(n as u64)?, *exit))
+            }
+            Kernel::Hailstone {
+                x,
+                steps,
+                odd,
+                exit,
+            } => {
+                let mut value = r[*x];
+                if value < 1 {
+                    return None;
+                }
+                let mut moves = 0i64;
+                let mut executed = 1u64;
+                while value != 1 {
+                    if value % 2 == 0 {
+                        value /= 2;
+                        executed += 5;
+                    } else {
+                        value = value.checked_mul(3)?.checked_add(1)?;
+                        executed += *odd;
+                    }
+                    moves += 1;
+                }
+                let steps_value = r[*steps].checked_add(moves)?;
+                Some((vec![(*x, 1), (*steps, steps_value)], executed, *exit))
+            }
+        }
+    }
+}
+
+impl Kernel {
+    pub fn describe(&self, registers: &[String]) -> String {
+        let r = |x: &usize| &registers[*x];
+        match self {
+            Self::Affine { maps, exit } => {
+                let maps = maps
+                    .iter()
+                    .map(|(x, mul, add)| format!("{} = {mul} * {} + {add}", r(x), r(x)))
+                    .collect::<Vec<_>>();
+                format!("{} then go to {exit}", maps.join(", "))
+            }
+            Self::Counted {
+                counter,
+                deltas,
+                exit,
+                ..
+            } => {
+                let deltas = deltas
+                    .iter()
+                    .map(|(x, delta)| format!("{} += {delta} * {}", r(x), r(counter)))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}, {} = 0 then go to {exit}",
+                    deltas.join(", "),
+                    r(counter)
+                )
+            }
+            Self::Hailstone { x, steps, exit, .. } => format!(
+                "{} += hailstone length of {}, {} = 1 then go to {exit}",
+                r(steps),
+                r(x),
+                r(x)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Limits;
+
+    fn compare(program: &Program, inputs: &[(&str, i64)]) {
+        let kernels = program.kernels();
+        let mut slow = Vm::new(program);
+        let mut fast = Vm::new(program);
+        for &(name, value) in inputs {
+            slow.set_register(name, value).unwrap();
+            fast.set_register(name, value).unwrap();
+        }
+        slow.run().unwrap();
+        fast.run_with(&kernels).unwrap();
+        assert_eq!(slow.registers, fast.registers, "{inputs:?}");
+        assert_eq!(slow.steps, fast.steps, "{inputs:?}");
+    }
+
+    #[test]
+    fn hailstone() -> anyhow::Result<()> {
+        let program = Program::assemble(
+            "
+                   tpl a
+                   inc a
+                   tpl a
+            start: jio a, done
+                   inc b
+                   jie a, half
+                   tpl a
+                   inc a
+                   jmp start
+            half:  hlf a
+                   jmp start
+            done:
+            ",
+            &["a", "b"],
+        )?;
+        let kernels = program.kernels();
+        // The odd branch of the loop is fused as well
+        assert_eq!(3, kernels.len());
+        assert_eq!(
+            Some("a = 9 * a + 3 then go to 3".to_owned()),
+            kernels.get(0).map(|k| k.describe(program.registers()))
+        );
+        assert_eq!(
+            Some("b += hailstone length of a, a = 1 then go to 11".to_owned()),
+            kernels.get(3).map(|k| k.describe(program.registers()))
+        );
+        for a in 0..50 {
+            compare(&program, &[("a", a), ("b", a % 3)]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn hailstone_by_way_of_next() -> anyhow::Result<()> {
+        let program = Program::assemble(
+            "
+            start: jio a, done
+                   inc b
+                   jie a, half
+                   tpl a
+                   inc a
+                   jmp next
+            half:  hlf a
+            next:  jmp start
+            done:
+            ",
+            &["a", "b"],
+        )?;
+        assert!(matches!(
+            program.kernels().get(0),
+            Some(Kernel::Hailstone { odd: 7, .. })
+        ));
+        for a in [3, 7, 27] {
+            compare(&program, &[("a", a)]);
+        }
+        let mut vm = Vm::new(&program);
+        vm.set_register("a", 27)?;
+        vm.run_with(&program.kernels())?;
+        assert_eq!(638, vm.steps);
+        Ok(())
+    }
+
+    #[test]
+    fn counted() -> anyhow::Result<()> {
+        let program = Program::assemble(
+            "
+                  cpy b c
+            loop: inc a
+                  add 3 d
+                  dec c
+                  jnz c loop
+                  out a
+            ",
+            &["a", "b", "c", "d"],
+        )?;
+        assert!(matches!(
+            program.kernels().get(1),
+            Some(Kernel::Counted { counter: 2, .. })
+        ));
+        for b in 1..20 {
+            compare(&program, &[("a", 5), ("b", b)]);
+        }
+
+        let mut vm = Vm::new(&program);
+        vm.set_register("b", 1_000_000_000_000)?;
+        vm.run_with(&program.kernels())?;
+        assert_eq!(Some(1_000_000_000_000), vm.register("a"));
+        assert_eq!(Some(3_000_000_000_000), vm.register("d"));
+        assert_eq!(4_000_000_000_002, vm.steps);
+
+        // A loop which would take the VM past its step limit is stepped through instead
+        let limits = Limits {
+            max_steps: Some(50),
+            ..Limits::default()
+        };
+        let mut vm = Vm::new(&program).with_limits(limits);
+        vm.set_register("b", 1_000)?;
+        assert_eq!(
+            Err(VmError::StepLimit { ip: 2, steps: 50 }),
+            vm.run_with(&program.kernels())
+        );
+        assert_eq!(Some(13), vm.register("a"));
+        Ok(())
+    }
+}
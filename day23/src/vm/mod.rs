@@ -0,0 +1,371 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+pub use debugger::Debugger;
+pub use flow::{Block, ControlFlow, Loop};
+pub use kernel::{Kernel, Kernels};
+pub use number::Number;
+pub use profile::{BlockProfile, Profile, Profiler};
+pub use trace::Tracer;
+
+mod asm;
+mod debugger;
+mod flow;
+mod kernel;
+mod number;
+mod profile;
+mod trace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Value(i64),
+    Register(usize),
+}
+
+// Jump offsets are relative to the jumping instruction; the assembler turns labels into
+// offsets. Registers are indices into the program's register names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Hlf(usize),
+    Tpl(usize),
+    Inc(usize),
+    Dec(usize),
+    Cpy(Param, usize),
+    Add(Param, usize),
+    Mul(Param, usize),
+    Jmp(Param),
+    Jie(usize, Param),
+    Jio(usize, Param),
+    Jnz(Param, Param),
+    Out(Param),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    registers: Vec<String>,
+    // Label names from the source, by address
+    labels: BTreeMap<usize, String>,
+}
+
+impl Program {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn registers(&self) -> &[String] {
+        &self.registers
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn register(&self, name: &str) -> Option<usize> {
+        self.registers.iter().position(|r| r == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    Wrapping,
+    // Stop with an error on overflow
+    #[default]
+    Checked,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    Overflow { ip: usize, instruction: Instruction },
+    StepLimit { ip: usize, steps: u64 },
+    Timeout { ip: usize, steps: u64 },
+}
+
+impl VmError {
+    pub fn ip(&self) -> usize {
+        match self {
+            Self::Overflow { ip, .. } | Self::StepLimit { ip, .. } | Self::Timeout { ip, .. } => {
+                *ip
+            }
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow { ip, instruction } => write!(f, "overflow at {ip}: {instruction:?}"),
+            Self::StepLimit { ip, steps } => write!(f, "stopped at {ip} after {steps} steps"),
+            Self::Timeout { ip, steps } => write!(f, "timed out at {ip} after {steps} steps"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+// Told about every instruction executed by Vm::run_observed
+pub trait Observer<N: Number> {
+    // Called after the instruction at `ip` has been executed
+    fn executed(&mut self, vm: &Vm<N>, ip: usize);
+}
+
+impl<N: Number> Observer<N> for () {
+    fn executed(&mut self, _: &Vm<N>, _: usize) {}
+}
+
+// How many steps to run between looks at the clock
+const CLOCK_INTERVAL: u64 = 1024;
+
+#[derive(Debug, Clone)]
+pub struct Vm<'a, N: Number = i64> {
+    program: &'a Program,
+    pub ip: usize,
+    pub registers: Vec<N>,
+    pub output: Vec<N>,
+    pub steps: u64,
+    pub arithmetic: Arithmetic,
+    pub limits: Limits,
+}
+
+impl<'a, N: Number> Vm<'a, N> {
+    pub fn new(program: &'a Program) -> Self {
+        Self {
+            program,
+            ip: 0,
+            registers: vec![N::from(0); program.registers.len()],
+            output: Vec::new(),
+            steps: 0,
+            arithmetic: Arithmetic::default(),
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn program(&self) -> &'a Program {
+        self.program
+    }
+
+    pub fn register(&self, name: &str) -> Option<N> {
+        self.program
+            .register(name)
+            .map(|r| self.registers[r].clone())
+    }
+
+    pub fn set_register(&mut self, name: &str, value: N) -> Result<()> {
+        let r = self
+            .program
+            .register(name)
+            .ok_or_else(|| anyhow!("no register {name}"))?;
+        self.registers[r] = value;
+        Ok(())
+    }
+
+    pub fn halted(&self) -> bool {
+        self.ip >= self.program.len()
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
+        self.run_observed(&mut ())
+    }
+
+    pub fn run_observed(&mut self, observer: &mut impl Observer<N>) -> Result<(), VmError> {
+        let started = Instant::now();
+        for round in 0.. {
+            if self.halted() {
+                break;
+            }
+            self.check_limits(started, round)?;
+            let ip = self.ip;
+            self.step()?;
+            observer.executed(self, ip);
+        }
+        Ok(())
+    }
+
+    // Fails if another step would break the limits. The clock is only read every so many
+    // rounds of the caller's loop.
+    fn check_limits(&self, started: Instant, round: u64) -> Result<(), VmError> {
+        let (ip, done) = (self.ip, self.steps);
+        if self.limits.max_steps.is_some_and(|max| done >= max) {
+            return Err(VmError::StepLimit { ip, steps: done });
+        }
+        if let Some(timeout) = self.limits.timeout {
+            if round.is_multiple_of(CLOCK_INTERVAL) && started.elapsed() > timeout {
+                return Err(VmError::Timeout { ip, steps: done });
+            }
+        }
+        Ok(())
+    }
+
+    // Execute the instruction at ip, returning false if the program had already halted.
+    // Nothing changes if the instruction fails.
+    pub fn step(&mut self) -> Result<bool, VmError> {
+        let Some(&instruction) = self.program.instructions.get(self.ip) else {
+            return Ok(false);
+        };
+        log::debug!("{}: {instruction:?} {:?}", self.ip, self.registers);
+        let wrapping = self.arithmetic == Arithmetic::Wrapping;
+        let ip = self.ip;
+        let checked = |value: Option<N>| value.ok_or(VmError::Overflow { ip, instruction });
+        let mut offset = 1;
+        match instruction {
+            Instruction::Hlf(x) => self.registers[x] = self.registers[x].half(),
+            Instruction::Tpl(x) => {
+                self.registers[x] = checked(self.registers[x].mul(&N::from(3), wrapping))?
+            }
+            Instruction::Inc(x) => {
+                self.registers[x] = checked(self.registers[x].add(&N::from(1), wrapping))?
+            }
+            Instruction::Dec(x) => {
+                self.registers[x] = checked(self.registers[x].add(&N::from(-1), wrapping))?
+            }
+            Instruction::Cpy(p, x) => self.registers[x] = self.value(p),
+            Instruction::Add(p, x) => {
+                self.registers[x] = checked(self.registers[x].add(&self.value(p), wrapping))?
+            }
+            Instruction::Mul(p, x) => {
+                self.registers[x] = checked(self.registers[x].mul(&self.value(p), wrapping))?
+            }
+            Instruction::Jmp(p) => offset = self.offset(p),
+            Instruction::Jie(x, p) if self.registers[x].is_even() => offset = self.offset(p),
+            Instruction::Jio(x, p) if self.registers[x] == N::from(1) => offset = self.offset(p),
+            Instruction::Jnz(c, p) if self.value(c) != N::from(0) => offset = self.offset(p),
+            Instruction::Jie(..) | Instruction::Jio(..) | Instruction::Jnz(..) => {}
+            Instruction::Out(p) => {
+                let value = self.value(p);
+                self.output.push(value);
+            }
+        }
+        self.steps += 1;
+        self.ip = match isize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.ip.checked_add_signed(offset))
+        {
+            Some(ip) if ip < self.program.len() => ip,
+            _ => self.program.len(),
+        };
+        Ok(true)
+    }
+
+    fn value(&self, param: Param) -> N {
+        match param {
+            Param::Value(v) => N::from(v),
+            Param::Register(r) => self.registers[r].clone(),
+        }
+    }
+
+    // Offsets too big to be held are off the end of the program
+    fn offset(&self, param: Param) -> i64 {
+        self.value(param).to_i64().unwrap_or(i64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use super::*;
+
+    #[test]
+    fn example() -> Result<()> {
+        let program = Program::assemble("inc a\njio a, +2\ntpl a\ninc a", &["a", "b"])?;
+        let mut vm = Vm::new(&program);
+        vm.run()?;
+        assert_eq!(Some(2), vm.register("a"));
+        assert_eq!(3, vm.steps);
+        assert_eq!(Ok(false), vm.step());
+        Ok(())
+    }
+
+    #[test]
+    fn assembunny() -> Result<()> {
+        let program = Program::assemble(
+            "
+            cpy 41 a
+            inc a
+            inc a
+            dec a
+            jnz a 2
+            dec a
+            cpy a c
+            mul 2 c
+            add a c
+            out c
+            ",
+            &[],
+        )?;
+        let mut vm = Vm::new(&program);
+        vm.run()?;
+        assert_eq!(Some(42), vm.register("a"));
+        assert_eq!(vec![126], vm.output);
+        Ok(())
+    }
+
+    #[test]
+    fn overflow() -> Result<()> {
+        let program = Program::assemble(
+            "inc a
+loop: tpl a
+jmp loop",
+            &["a"],
+        )?;
+        let mut vm = Vm::new(&program);
+        let error = vm.run().unwrap_err();
+        assert_eq!(
+            VmError::Overflow {
+                ip: 1,
+                instruction: Instruction::Tpl(0)
+            },
+            error
+        );
+        assert_eq!(1, error.ip());
+        assert_eq!(Some(3i64.pow(39)), vm.register("a"));
+
+        let mut vm = Vm::new(&program).with_arithmetic(Arithmetic::Wrapping);
+        vm.limits.max_steps = Some(81);
+        assert_eq!(Err(VmError::StepLimit { ip: 1, steps: 81 }), vm.run());
+        assert_eq!(Some(3i64.wrapping_pow(40)), vm.register("a"));
+
+        let mut vm = Vm::<BigInt>::new(&program);
+        vm.limits.max_steps = Some(201);
+        assert!(vm.run().is_err());
+        assert_eq!(Some(BigInt::from(3).pow(100)), vm.register("a"));
+        Ok(())
+    }
+
+    #[test]
+    fn timeout() -> Result<()> {
+        let program = Program::assemble("jmp 0", &[])?;
+        let limits = Limits {
+            timeout: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        let mut vm: Vm = Vm::new(&program).with_limits(limits);
+        assert!(matches!(vm.run(), Err(VmError::Timeout { ip: 0, .. })));
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use num_bigint::BigInt;
+
+// What a register holds. Machine integers either wrap or report overflow, depending on
+// the VM's arithmetic; big integers never overflow.
+pub trait Number: Clone + PartialEq + fmt::Debug + fmt::Display + From<i64> {
+    fn add(&self, other: &Self, wrapping: bool) -> Option<Self>;
+    fn mul(&self, other: &Self, wrapping: bool) -> Option<Self>;
+    fn half(&self) -> Self;
+    fn is_even(&self) -> bool;
+    // For use as a jump offset
+    fn to_i64(&self) -> Option<i64>;
+}
+
+impl Number for i64 {
+    fn add(&self, other: &Self, wrapping: bool) -> Option<Self> {
+        match wrapping {
+            true => Some(self.wrapping_add(*other)),
+            false => self.checked_add(*other),
+        }
+    }
+
+    fn mul(&self, other: &Self, wrapping: bool) -> Option<Self> {
+        match wrapping {
+            true => Some(self.wrapping_mul(*other)),
+            false => self.checked_mul(*other),
+        }
+    }
+
+    fn half(&self) -> Self {
+        self / 2
+    }
+
+    fn is_even(&self) -> bool {
+        self % 2 == 0
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+}
+
+impl Number for BigInt {
+    fn add(&self, other: &Self, _: bool) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn mul(&self, other: &Self, _: bool) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn half(&self) -> Self {
+        self / 2
+    }
+
+    fn is_even(&self) -> bool {
+        !self.bit(0)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        self.try_into().ok()
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use super::{Number, Observer, Program, Vm};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProfile {
+    pub start: usize,
+    pub end: usize,
+    // Times control arrived at the first instruction
+    pub entries: u64,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    // Executions of each instruction, by address
+    pub hits: Vec<u64>,
+    pub blocks: Vec<BlockProfile>,
+}
+
+// Counts instructions and times the basic blocks of a program as it runs. Time is
+// measured between one instruction finishing and the next, and charged to the block of
+// the later one.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    // Index into blocks for each address
+    block_of: Vec<usize>,
+    profile: Profile,
+    last: Instant,
+}
+
+impl Profiler {
+    pub fn new(program: &Program) -> Self {
+        let mut block_of = vec![0; program.len()];
+        let blocks = program
+            .control_flow()
+            .blocks
+            .into_iter()
+            .enumerate()
+            .map(|(idx, block)| {
+                block_of[block.start..block.end].fill(idx);
+                BlockProfile {
+                    start: block.start,
+                    end: block.end,
+                    entries: 0,
+                    time: Duration::ZERO,
+                }
+            })
+            .collect();
+        Self {
+            block_of,
+            profile: Profile {
+                hits: vec![0; program.len()],
+                blocks,
+            },
+            last: Instant::now(),
+        }
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn finish(self) -> Profile {
+        self.profile
+    }
+}
+
+impl<N: Number> Observer<N> for Profiler {
+    fn executed(&mut self, _: &Vm<N>, ip: usize) {
+        let now = Instant::now();
+        let block = &mut self.profile.blocks[self.block_of[ip]];
+        if ip == block.start {
+            block.entries += 1;
+        }
+        block.time += now - self.last;
+        self.profile.hits[ip] += 1;
+        self.last = now;
+    }
+}
+
+impl Profile {
+    // Blocks with the most time spent in them first
+    pub fn hottest(&self) -> Vec<&BlockProfile> {
+        let mut blocks = self.blocks.iter().collect::<Vec<_>>();
+        blocks.sort_by(|a, b| b.time.cmp(&a.time).then(a.start.cmp(&b.start)));
+        blocks
+    }
+
+    // The profile alongside the program's listing
+    pub fn annotate<'a>(&'a self, program: &'a Program) -> impl fmt::Display + 'a {
+        Annotated {
+            profile: self,
+            program,
+        }
+    }
+}
+
+struct Annotated<'a> {
+    profile: &'a Profile,
+    program: &'a Program,
+}
+
+impl fmt::Display for Annotated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let listings = self.program.listings();
+        for block in &self.profile.blocks {
+            writeln!(
+                f,
+                "block {}..{}: entered {} times, {:?}",
+                block.start, block.end, block.entries, block.time
+            )?;
+            for (address, listing) in listings
+                .iter()
+                .enumerate()
+                .take(block.end)
+                .skip(block.start)
+            {
+                writeln!(
+                    f,
+                    "{:>12} {address:4}: {listing}",
+                    self.profile.hits[address]
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() -> anyhow::Result<()> {
+        let program = Program::assemble(
+            "
+                  cpy 3 c
+            loop: inc a
+                  dec c
+                  jnz c loop
+                  out a
+            ",
+            &["a", "b", "c"],
+        )?;
+        let mut profiler = Profiler::new(&program);
+        let mut vm: Vm = Vm::new(&program);
+        vm.run_observed(&mut profiler)?;
+        let profile = profiler.finish();
+        assert_eq!(vec![1, 3, 3, 3, 1], profile.hits);
+        let entries = profile
+            .blocks
+            .iter()
+            .map(|b| (b.start, b.end, b.entries))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 1, 1), (1, 4, 3), (4, 5, 1)], entries);
+        assert_eq!(vm.steps, profile.hits.iter().sum::<u64>());
+        Ok(())
+    }
+}
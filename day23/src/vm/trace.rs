@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+
+use serde_json::{json, Map, Value};
+
+use super::{Number, Observer, Program, Vm};
+
+// Writes a JSON line for every instruction executed, giving the step number, address,
+// instruction and the registers afterwards, e.g.
+//   {"instruction":"inc a","ip":0,"registers":{"a":1,"b":0},"step":1}
+// Writing stops at the first error, which is returned by finish.
+pub struct Tracer<W: Write> {
+    out: W,
+    listing: Vec<String>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(program: &Program, out: W) -> Self {
+        Self {
+            out,
+            listing: program.listings(),
+            error: None,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => {
+                self.out.flush()?;
+                Ok(self.out)
+            }
+        }
+    }
+}
+
+impl<W: Write, N: Number> Observer<N> for Tracer<W> {
+    fn executed(&mut self, vm: &Vm<N>, ip: usize) {
+        if self.error.is_some() {
+            return;
+        }
+        let registers = vm
+            .program()
+            .registers()
+            .iter()
+            .zip(&vm.registers)
+            .map(|(name, value)| {
+                let value = match value.to_i64() {
+                    Some(v) => json!(v),
+                    None => json!(value.to_string()),
+                };
+                (name.to_owned(), value)
+            })
+            .collect::<Map<_, _>>();
+        let line = json!({
+            "step": vm.steps,
+            "ip": ip,
+            "instruction": self.listing[ip],
+            "registers": Value::Object(registers),
+        });
+        if let Err(e) = writeln!(self.out, "{line}") {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() -> anyhow::Result<()> {
+        let program = Program::assemble("inc a\njio a, done\ntpl a\ndone: inc b", &["a", "b"])?;
+        let mut tracer = Tracer::new(&program, Vec::new());
+        Vm::<i64>::new(&program).run_observed(&mut tracer)?;
+        let text = String::from_utf8(tracer.finish()?)?;
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(3, lines.len());
+        assert_eq!(
+            r#"{"instruction":"jio a, done","ip":1,"registers":{"a":1,"b":0},"step":2}"#,
+            lines[1]
+        );
+        let last: Value = serde_json::from_str(lines[2])?;
+        assert_eq!(json!({"a": 1, "b": 1}), last["registers"]);
+        Ok(())
+    }
+}
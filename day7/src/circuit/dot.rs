@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fmt::Write};
+
+use super::{Circuit, Operand, Word};
+
+impl<W: Word> Circuit<W> {
+    // Graphviz rendering with a node per gate and an edge per wire. Undriven wires become
+    // input nodes and wires which feed nothing become output nodes. Edges are labelled
+    // with the wire name and, when given, its value; the gates which drive `highlight`
+    // are drawn in red.
+    pub fn to_dot(&self, values: Option<&HashMap<String, W>>, highlight: Option<&str>) -> String {
+        let mut cone = vec![false; self.names.len()];
+        let mut pending = highlight
+            .and_then(|wire| self.wires.get(wire))
+            .copied()
+            .into_iter()
+            .collect::<Vec<_>>();
+        while let Some(wire) = pending.pop() {
+            if !cone[wire] {
+                cone[wire] = true;
+                pending.extend(self.inputs(wire));
+            }
+        }
+
+        let mut consumed = vec![false; self.names.len()];
+        for wire in 0..self.names.len() {
+            for input in self.inputs(wire) {
+                consumed[input] = true;
+            }
+        }
+
+        let label = |wire: usize| match values.and_then(|v| v.get(&self.names[wire])) {
+            Some(value) => format!("{} = {value}", self.names[wire]),
+            None => self.names[wire].to_owned(),
+        };
+        let colour = |wire: usize| if cone[wire] { ", color=red" } else { "" };
+
+        let mut dot = String::from("digraph circuit {\n    rankdir=LR;\n");
+        for (wire, driver) in self.drivers.iter().enumerate() {
+            match driver {
+                Some(gate) => {
+                    writeln!(
+                        dot,
+                        "    g{wire} [label=\"{}\", shape=box{}];",
+                        gate.name(),
+                        colour(wire)
+                    )
+                    .unwrap();
+                }
+                None => {
+                    writeln!(
+                        dot,
+                        "    g{wire} [label=\"{}\", shape=invhouse{}];",
+                        self.names[wire],
+                        colour(wire)
+                    )
+                    .unwrap();
+                }
+            }
+            if !consumed[wire] && driver.is_some() {
+                writeln!(
+                    dot,
+                    "    out{wire} [label=\"{}\", shape=house{}];",
+                    self.names[wire],
+                    colour(wire)
+                )
+                .unwrap();
+                writeln!(
+                    dot,
+                    "    g{wire} -> out{wire} [label=\"{}\"{}];",
+                    label(wire),
+                    colour(wire)
+                )
+                .unwrap();
+            }
+        }
+        for (wire, driver) in self.drivers.iter().enumerate() {
+            let Some(gate) = driver else {
+                continue;
+            };
+            for (idx, operand) in gate.inputs().into_iter().enumerate() {
+                match operand {
+                    Operand::Wire(input) => {
+                        writeln!(
+                            dot,
+                            "    g{input} -> g{wire} [label=\"{}\"{}];",
+                            label(input),
+                            colour(input)
+                        )
+                        .unwrap();
+                    }
+                    Operand::Value(value) => {
+                        writeln!(
+                            dot,
+                            "    c{wire}_{idx} [label=\"{value}\", shape=plaintext];"
+                        )
+                        .unwrap();
+                        writeln!(dot, "    c{wire}_{idx} -> g{wire};").unwrap();
+                    }
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{circuit::Circuit, Instruction};
+    use std::str::FromStr;
+
+    #[test]
+    fn dot() -> anyhow::Result<()> {
+        let instructions = ["x AND y -> a", "123 -> x", "NOT z -> w"]
+            .iter()
+            .map(|line| Instruction::from_str(line))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let circuit: Circuit = Circuit::new(&instructions)?;
+        let values = circuit.evaluate_with(&[("y", 7), ("z", 0)])?;
+        let dot = circuit.to_dot(Some(&values), Some("a"));
+        for line in [
+            "g0 [label=\"SET\", shape=box, color=red];",
+            "g1 [label=\"y\", shape=invhouse, color=red];",
+            "g2 [label=\"AND\", shape=box, color=red];",
+            "out2 [label=\"a\", shape=house, color=red];",
+            "g2 -> out2 [label=\"a = 3\", color=red];",
+            "g0 -> g2 [label=\"x = 123\", color=red];",
+            "g1 -> g2 [label=\"y = 7\", color=red];",
+            "c0_0 [label=\"123\", shape=plaintext];",
+            "c0_0 -> g0;",
+            "g3 [label=\"z\", shape=invhouse];",
+            "g4 [label=\"NOT\", shape=box];",
+            "g3 -> g4 [label=\"z = 0\"];",
+            "g4 -> out4 [label=\"w = 65535\"];",
+        ] {
+            assert!(dot.contains(line), "missing {line} in {dot}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use super::{Circuit, CircuitError, Operand, Word};
+
+// A circuit with the current value of every wire, kept up to date as inputs are set so
+// that only the downstream cone of a change is recomputed. Wires with an undriven input
+// have no value.
+#[derive(Debug, Clone)]
+pub struct LiveCircuit<W: Word = u16> {
+    circuit: Circuit<W>,
+    values: Vec<Option<W>>,
+    forced: Vec<Option<W>>,
+    consumers: Vec<Vec<usize>>,
+    // Position of each wire in the evaluation order
+    rank: Vec<usize>,
+    changed: Vec<usize>,
+}
+
+impl<W: Word> LiveCircuit<W> {
+    pub fn new(circuit: Circuit<W>) -> Self {
+        let mut rank = vec![0; circuit.names.len()];
+        for (position, &wire) in circuit.order.iter().enumerate() {
+            rank[wire] = position;
+        }
+        let mut live = Self {
+            values: vec![None; circuit.names.len()],
+            forced: vec![None; circuit.names.len()],
+            consumers: circuit.consumers(),
+            rank,
+            changed: Vec::new(),
+            circuit,
+        };
+        for idx in 0..live.circuit.order.len() {
+            let wire = live.circuit.order[idx];
+            live.values[wire] = live.value(wire);
+        }
+        live
+    }
+
+    pub fn circuit(&self) -> &Circuit<W> {
+        &self.circuit
+    }
+
+    // Drive wire with a fixed value in place of its gate, recomputing everything downstream.
+    pub fn set_input(&mut self, wire: &str, value: W) -> Result<(), CircuitError> {
+        let wire = self.circuit.index(wire)?;
+        self.forced[wire] = Some(value);
+        self.propagate(wire);
+        Ok(())
+    }
+
+    // Return wire to being driven by its gate, if it has one.
+    pub fn clear_input(&mut self, wire: &str) -> Result<(), CircuitError> {
+        let wire = self.circuit.index(wire)?;
+        self.forced[wire] = None;
+        self.propagate(wire);
+        Ok(())
+    }
+
+    pub fn current(&self, wire: &str) -> Result<Option<W>, CircuitError> {
+        Ok(self.values[self.circuit.index(wire)?])
+    }
+
+    // Wires whose value changed in the last set_input or clear_input, in evaluation order.
+    pub fn changed(&self) -> impl Iterator<Item = (&str, Option<W>)> {
+        self.changed
+            .iter()
+            .map(|&wire| (self.circuit.names[wire].as_str(), self.values[wire]))
+    }
+
+    // As Circuit::evaluate, with the inputs set so far
+    pub fn evaluate(&self) -> Result<HashMap<String, W>, CircuitError> {
+        self.circuit.evaluate_with(&self.inputs())
+    }
+
+    // As Circuit::probe, with the inputs set so far
+    pub fn probe(&self, wire: &str) -> Result<W, CircuitError> {
+        self.circuit.probe(wire, &self.inputs())
+    }
+
+    fn inputs(&self) -> Vec<(&str, W)> {
+        self.forced
+            .iter()
+            .enumerate()
+            .filter_map(|(wire, value)| value.map(|v| (self.circuit.names[wire].as_str(), v)))
+            .collect()
+    }
+
+    fn value(&self, wire: usize) -> Option<W> {
+        if let Some(value) = self.forced[wire] {
+            return Some(value);
+        }
+        self.circuit.drivers[wire].as_ref().and_then(|gate| {
+            gate.eval(|operand| match operand {
+                Operand::Value(v) => Ok(v),
+                Operand::Wire(input) => self.values[input].ok_or(()),
+            })
+            .ok()
+        })
+    }
+
+    fn propagate(&mut self, start: usize) {
+        self.changed.clear();
+        let mut queued = HashSet::from([start]);
+        let mut pending = BinaryHeap::from([Reverse((self.rank[start], start))]);
+        while let Some(Reverse((_, wire))) = pending.pop() {
+            let value = self.value(wire);
+            if value == self.values[wire] {
+                continue;
+            }
+            self.values[wire] = value;
+            self.changed.push(wire);
+            for &consumer in &self.consumers[wire] {
+                if queued.insert(consumer) {
+                    pending.push(Reverse((self.rank[consumer], consumer)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+    use std::str::FromStr;
+
+    fn circuit(lines: &[&str]) -> LiveCircuit {
+        let instructions = lines
+            .iter()
+            .map(|line| Instruction::from_str(line).unwrap())
+            .collect::<Vec<_>>();
+        LiveCircuit::new(Circuit::new(&instructions).unwrap())
+    }
+
+    fn changed(circuit: &LiveCircuit) -> Vec<(&str, Option<u16>)> {
+        circuit.changed().collect()
+    }
+
+    #[test]
+    fn downstream_cone() -> anyhow::Result<()> {
+        let mut circuit = circuit(&[
+            "x AND y -> d",
+            "d LSHIFT 1 -> a",
+            "NOT x -> h",
+            "123 -> x",
+            "z OR 1 -> y",
+        ]);
+        assert_eq!(Some(65412), circuit.current("h")?);
+        assert_eq!(None, circuit.current("a")?);
+
+        circuit.set_input("z", 0)?;
+        assert_eq!(
+            vec![
+                ("z", Some(0)),
+                ("y", Some(1)),
+                ("d", Some(1)),
+                ("a", Some(2))
+            ],
+            changed(&circuit)
+        );
+        circuit.set_input("z", 0xfe)?;
+        assert_eq!(Some(246), circuit.current("a")?);
+        assert_eq!(246, circuit.probe("a")?);
+        assert_eq!(Some(&246), circuit.evaluate()?.get("a"));
+        assert_eq!(
+            circuit.circuit().probe("a", &[("z", 0xfe)])?,
+            circuit.probe("a")?
+        );
+
+        // 123 AND 0x7f is the same as 123 AND 0xff, so nothing past y changes
+        circuit.set_input("z", 0x7e)?;
+        assert_eq!(
+            vec![("z", Some(0x7e)), ("y", Some(0x7f))],
+            changed(&circuit)
+        );
+        circuit.set_input("z", 0x7e)?;
+        assert!(changed(&circuit).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn override_and_restore() -> anyhow::Result<()> {
+        let mut circuit = circuit(&["123 -> x", "NOT x -> h", "x OR 4 -> a"]);
+        circuit.set_input("x", 3)?;
+        assert_eq!(
+            vec![("x", Some(3)), ("h", Some(65532)), ("a", Some(7))],
+            changed(&circuit)
+        );
+        assert_eq!(3, circuit.evaluate()?["x"]);
+        circuit.clear_input("x")?;
+        assert_eq!(Some(123), circuit.current("x")?);
+        assert_eq!(127, circuit.probe("a")?);
+        assert_eq!(Some(127), circuit.current("a")?);
+        assert_eq!(
+            Err(CircuitError::UnknownWire("q".to_owned())),
+            circuit.set_input("q", 1)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,597 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use crate::{Instruction, Signal};
+
+pub use live::LiveCircuit;
+pub use optimise::OptimiseReport;
+pub use word::Word;
+
+mod dot;
+mod live;
+mod optimise;
+mod word;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitError {
+    MultiplyDriven(String),
+    Cycle(Vec<String>),
+    Undriven(String),
+    UnknownWire(String),
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MultiplyDriven(wire) => write!(f, "wire {wire} has more than one driver"),
+            Self::Cycle(wires) => write!(f, "wires form a cycle: {}", wires.join(" -> ")),
+            Self::Undriven(wire) => write!(f, "wire {wire} is used but never driven"),
+            Self::UnknownWire(wire) => write!(f, "there is no wire {wire}"),
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand<W> {
+    Value(W),
+    Wire(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Gate<W> {
+    Set(Operand<W>),
+    Not(Operand<W>),
+    And(Operand<W>, Operand<W>),
+    Or(Operand<W>, Operand<W>),
+    Xor(Operand<W>, Operand<W>),
+    Nand(Operand<W>, Operand<W>),
+    Nor(Operand<W>, Operand<W>),
+    LShift(Operand<W>, Operand<W>),
+    RShift(Operand<W>, Operand<W>),
+    LRotate(Operand<W>, Operand<W>),
+    RRotate(Operand<W>, Operand<W>),
+    Add(Operand<W>, Operand<W>),
+    Sub(Operand<W>, Operand<W>),
+}
+
+impl<W: Word> Gate<W> {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Set(_) => "SET",
+            Self::Not(_) => "NOT",
+            Self::And(..) => "AND",
+            Self::Or(..) => "OR",
+            Self::Xor(..) => "XOR",
+            Self::Nand(..) => "NAND",
+            Self::Nor(..) => "NOR",
+            Self::LShift(..) => "LSHIFT",
+            Self::RShift(..) => "RSHIFT",
+            Self::LRotate(..) => "LROTATE",
+            Self::RRotate(..) => "RROTATE",
+            Self::Add(..) => "ADD",
+            Self::Sub(..) => "SUB",
+        }
+    }
+
+    fn inputs(&self) -> Vec<Operand<W>> {
+        match *self {
+            Self::Set(s) | Self::Not(s) => vec![s],
+            Self::And(s1, s2)
+            | Self::Or(s1, s2)
+            | Self::Xor(s1, s2)
+            | Self::Nand(s1, s2)
+            | Self::Nor(s1, s2)
+            | Self::LShift(s1, s2)
+            | Self::RShift(s1, s2)
+            | Self::LRotate(s1, s2)
+            | Self::RRotate(s1, s2)
+            | Self::Add(s1, s2)
+            | Self::Sub(s1, s2) => vec![s1, s2],
+        }
+    }
+
+    fn map_inputs(&self, mut f: impl FnMut(Operand<W>) -> Operand<W>) -> Self {
+        match *self {
+            Self::Set(s) => Self::Set(f(s)),
+            Self::Not(s) => Self::Not(f(s)),
+            Self::And(s1, s2) => Self::And(f(s1), f(s2)),
+            Self::Or(s1, s2) => Self::Or(f(s1), f(s2)),
+            Self::Xor(s1, s2) => Self::Xor(f(s1), f(s2)),
+            Self::Nand(s1, s2) => Self::Nand(f(s1), f(s2)),
+            Self::Nor(s1, s2) => Self::Nor(f(s1), f(s2)),
+            Self::LShift(s1, s2) => Self::LShift(f(s1), f(s2)),
+            Self::RShift(s1, s2) => Self::RShift(f(s1), f(s2)),
+            Self::LRotate(s1, s2) => Self::LRotate(f(s1), f(s2)),
+            Self::RRotate(s1, s2) => Self::RRotate(f(s1), f(s2)),
+            Self::Add(s1, s2) => Self::Add(f(s1), f(s2)),
+            Self::Sub(s1, s2) => Self::Sub(f(s1), f(s2)),
+        }
+    }
+
+    fn eval<E>(&self, v: impl Fn(Operand<W>) -> Result<W, E>) -> Result<W, E> {
+        let value = match *self {
+            Self::Set(s) => v(s)?,
+            Self::Not(s) => !v(s)?,
+            Self::And(s1, s2) => v(s1)? & v(s2)?,
+            Self::Or(s1, s2) => v(s1)? | v(s2)?,
+            Self::Xor(s1, s2) => v(s1)? ^ v(s2)?,
+            Self::Nand(s1, s2) => !(v(s1)? & v(s2)?),
+            Self::Nor(s1, s2) => !(v(s1)? | v(s2)?),
+            Self::LShift(s1, s2) => v(s1)?.shift_left(v(s2)?),
+            Self::RShift(s1, s2) => v(s1)?.shift_right(v(s2)?),
+            Self::LRotate(s1, s2) => v(s1)?.rotate_left(v(s2)?),
+            Self::RRotate(s1, s2) => v(s1)?.rotate_right(v(s2)?),
+            Self::Add(s1, s2) => v(s1)?.wrapping_add(v(s2)?),
+            Self::Sub(s1, s2) => v(s1)?.wrapping_sub(v(s2)?),
+        };
+        Ok(value)
+    }
+}
+
+// The wires of a circuit, each driven by at most one gate, with the gates sorted so that
+// every wire is evaluated after all of its inputs. Wires carry 16 bit words unless
+// another width is chosen.
+#[derive(Debug, Clone)]
+pub struct Circuit<W: Word = u16> {
+    names: Vec<String>,
+    wires: HashMap<String, usize>,
+    drivers: Vec<Option<Gate<W>>>,
+    order: Vec<usize>,
+}
+
+impl<W: Word> Circuit<W> {
+    pub fn new(instructions: &[Instruction<W>]) -> Result<Self, CircuitError> {
+        Self::with_wires(instructions, &[])
+    }
+
+    // A circuit which also has the given wires, even if no instruction mentions them
+    fn with_wires(instructions: &[Instruction<W>], wires: &[&str]) -> Result<Self, CircuitError> {
+        let mut circuit = Self {
+            names: Vec::new(),
+            wires: HashMap::new(),
+            drivers: Vec::new(),
+            order: Vec::new(),
+        };
+        for instruction in instructions {
+            let gate = match instruction {
+                Instruction::Set(_, s) => Gate::Set(circuit.operand(s)),
+                Instruction::Not(_, s) => Gate::Not(circuit.operand(s)),
+                Instruction::And(_, s1, s2) => Gate::And(circuit.operand(s1), circuit.operand(s2)),
+                Instruction::Or(_, s1, s2) => Gate::Or(circuit.operand(s1), circuit.operand(s2)),
+                Instruction::Xor(_, s1, s2) => Gate::Xor(circuit.operand(s1), circuit.operand(s2)),
+                Instruction::Nand(_, s1, s2) => {
+                    Gate::Nand(circuit.operand(s1), circuit.operand(s2))
+                }
+                Instruction::Nor(_, s1, s2) => Gate::Nor(circuit.operand(s1), circuit.operand(s2)),
+                Instruction::LShift(_, s1, s2) => {
+                    Gate::LShift(circuit.operand(s1), circuit.operand(s2))
+                }
+                Instruction::RShift(_, s1, s2) => {
+                    Gate::RShift(circuit.operand(s1), circuit.operand(s2))
+                }
+                Instruction::LRotate(_, s1, s2) => {
+                    Gate::LRotate(circuit.operand(s1), circuit.operand(s2))
+                }
+                Instruction::RRotate(_, s1, s2) => {
+                    Gate::RRotate(circuit.operand(s1), circuit.operand(s2))
+                }
+                Instruction::Add(_, s1, s2) => Gate::Add(circuit.operand(s1), circuit.operand(s2)),
+                Instruction::Sub(_, s1, s2) => Gate::Sub(circuit.operand(s1), circuit.operand(s2)),
+            };
+            let target = circuit.wire(&instruction.get_target());
+            if circuit.drivers[target].is_some() {
+                return Err(CircuitError::MultiplyDriven(instruction.get_target()));
+            }
+            circuit.drivers[target] = Some(gate);
+        }
+        for wire in wires {
+            circuit.wire(wire);
+        }
+        circuit.order = circuit.sort()?;
+        Ok(circuit)
+    }
+
+    pub fn wires(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|name| name.as_str())
+    }
+
+    pub fn evaluate(&self) -> Result<HashMap<String, W>, CircuitError> {
+        self.evaluate_with(&[])
+    }
+
+    // Values of every wire, with each overridden wire ignoring its driver and carrying
+    // the given value instead.
+    pub fn evaluate_with(
+        &self,
+        overrides: &[(&str, W)],
+    ) -> Result<HashMap<String, W>, CircuitError> {
+        let values = self.run(&self.overrides(overrides)?, None)?;
+        Ok(values
+            .into_iter()
+            .enumerate()
+            .filter_map(|(wire, value)| value.map(|v| (self.names[wire].to_owned(), v)))
+            .collect())
+    }
+
+    // Values of the given wires and everything they depend on, so that an undriven wire
+    // is only an error if one of them needs it.
+    pub fn evaluate_cone(
+        &self,
+        outputs: &[&str],
+        overrides: &[(&str, W)],
+    ) -> Result<HashMap<String, W>, CircuitError> {
+        let outputs = outputs
+            .iter()
+            .map(|wire| self.index(wire))
+            .collect::<Result<Vec<_>, _>>()?;
+        let overrides = self.overrides(overrides)?;
+        let needed = self.cone(outputs.iter().copied(), &overrides);
+        let values = self.run(&overrides, Some(&needed))?;
+        for wire in outputs {
+            if values[wire].is_none() {
+                return Err(CircuitError::Undriven(self.names[wire].to_owned()));
+            }
+        }
+        Ok(values
+            .into_iter()
+            .enumerate()
+            .filter(|&(wire, _)| needed[wire])
+            .filter_map(|(wire, value)| value.map(|v| (self.names[wire].to_owned(), v)))
+            .collect())
+    }
+
+    // Value of a single wire, only evaluating the gates which it depends on.
+    pub fn probe(&self, wire: &str, overrides: &[(&str, W)]) -> Result<W, CircuitError> {
+        Ok(self.evaluate_cone(&[wire], overrides)?[wire])
+    }
+
+    // The wires feeding the targets, stopping at overridden wires
+    fn cone(&self, targets: impl Iterator<Item = usize>, overrides: &[Option<W>]) -> Vec<bool> {
+        let mut needed = vec![false; self.names.len()];
+        let mut pending = targets.collect::<Vec<_>>();
+        while let Some(wire) = pending.pop() {
+            if needed[wire] {
+                continue;
+            }
+            needed[wire] = true;
+            if overrides[wire].is_none() {
+                pending.extend(self.inputs(wire));
+            }
+        }
+        needed
+    }
+
+    fn index(&self, wire: &str) -> Result<usize, CircuitError> {
+        self.wires
+            .get(wire)
+            .copied()
+            .ok_or_else(|| CircuitError::UnknownWire(wire.to_owned()))
+    }
+
+    fn overrides(&self, overrides: &[(&str, W)]) -> Result<Vec<Option<W>>, CircuitError> {
+        let mut values = vec![None; self.names.len()];
+        for (wire, value) in overrides {
+            values[self.index(wire)?] = Some(*value);
+        }
+        Ok(values)
+    }
+
+    fn run(
+        &self,
+        overrides: &[Option<W>],
+        needed: Option<&[bool]>,
+    ) -> Result<Vec<Option<W>>, CircuitError> {
+        let mut values = overrides.to_vec();
+        for &wire in &self.order {
+            if overrides[wire].is_some() || needed.is_some_and(|needed| !needed[wire]) {
+                continue;
+            }
+            if let Some(gate) = &self.drivers[wire] {
+                values[wire] = Some(self.apply(gate, &values)?);
+            }
+        }
+        Ok(values)
+    }
+
+    fn wire(&mut self, name: &str) -> usize {
+        if let Some(&wire) = self.wires.get(name) {
+            return wire;
+        }
+        self.names.push(name.to_owned());
+        self.drivers.push(None);
+        self.wires.insert(name.to_owned(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn operand(&mut self, signal: &Signal<W>) -> Operand<W> {
+        match signal {
+            Signal::Value(v) => Operand::Value(*v),
+            Signal::Wire(name) => Operand::Wire(self.wire(name)),
+        }
+    }
+
+    fn value(&self, operand: Operand<W>, values: &[Option<W>]) -> Result<W, CircuitError> {
+        match operand {
+            Operand::Value(v) => Ok(v),
+            Operand::Wire(wire) => {
+                values[wire].ok_or_else(|| CircuitError::Undriven(self.names[wire].to_owned()))
+            }
+        }
+    }
+
+    fn apply(&self, gate: &Gate<W>, values: &[Option<W>]) -> Result<W, CircuitError> {
+        gate.eval(|operand| self.value(operand, values))
+    }
+
+    fn signal(&self, operand: Operand<W>) -> Signal<W> {
+        match operand {
+            Operand::Value(v) => Signal::Value(v),
+            Operand::Wire(wire) => Signal::Wire(self.names[wire].to_owned()),
+        }
+    }
+
+    fn instruction(&self, wire: usize, gate: &Gate<W>) -> Instruction<W> {
+        let t = self.names[wire].to_owned();
+        match *gate {
+            Gate::Set(s) => Instruction::Set(t, self.signal(s)),
+            Gate::Not(s) => Instruction::Not(t, self.signal(s)),
+            Gate::And(s1, s2) => Instruction::And(t, self.signal(s1), self.signal(s2)),
+            Gate::Or(s1, s2) => Instruction::Or(t, self.signal(s1), self.signal(s2)),
+            Gate::Xor(s1, s2) => Instruction::Xor(t, self.signal(s1), self.signal(s2)),
+            Gate::Nand(s1, s2) => Instruction::Nand(t, self.signal(s1), self.signal(s2)),
+            Gate::Nor(s1, s2) => Instruction::Nor(t, self.signal(s1), self.signal(s2)),
+            Gate::LShift(s1, s2) => Instruction::LShift(t, self.signal(s1), self.signal(s2)),
+            Gate::RShift(s1, s2) => Instruction::RShift(t, self.signal(s1), self.signal(s2)),
+            Gate::LRotate(s1, s2) => Instruction::LRotate(t, self.signal(s1), self.signal(s2)),
+            Gate::RRotate(s1, s2) => Instruction::RRotate(t, self.signal(s1), self.signal(s2)),
+            Gate::Add(s1, s2) => Instruction::Add(t, self.signal(s1), self.signal(s2)),
+            Gate::Sub(s1, s2) => Instruction::Sub(t, self.signal(s1), self.signal(s2)),
+        }
+    }
+
+    // The gates as instructions, in evaluation order
+    pub fn instructions(&self) -> Vec<Instruction<W>> {
+        self.order
+            .iter()
+            .filter_map(|&wire| {
+                self.drivers[wire]
+                    .as_ref()
+                    .map(|gate| self.instruction(wire, gate))
+            })
+            .collect()
+    }
+
+    fn inputs(&self, wire: usize) -> impl Iterator<Item = usize> + '_ {
+        self.drivers[wire]
+            .iter()
+            .flat_map(|gate| gate.inputs())
+            .filter_map(|operand| match operand {
+                Operand::Wire(input) => Some(input),
+                Operand::Value(_) => None,
+            })
+    }
+
+    fn consumers(&self) -> Vec<Vec<usize>> {
+        let mut consumers = vec![Vec::new(); self.names.len()];
+        for wire in 0..self.names.len() {
+            for input in self.inputs(wire) {
+                consumers[input].push(wire);
+            }
+        }
+        consumers
+    }
+
+    // Kahn's algorithm; any wires left over are on, or downstream of, a cycle
+    fn sort(&self) -> Result<Vec<usize>, CircuitError> {
+        let mut pending_inputs = (0..self.names.len())
+            .map(|wire| self.inputs(wire).count())
+            .collect::<Vec<_>>();
+        let consumers = self.consumers();
+        let mut ready = (0..self.names.len())
+            .filter(|&wire| pending_inputs[wire] == 0)
+            .collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(self.names.len());
+        while let Some(wire) = ready.pop_front() {
+            order.push(wire);
+            for &consumer in &consumers[wire] {
+                pending_inputs[consumer] -= 1;
+                if pending_inputs[consumer] == 0 {
+                    ready.push_back(consumer);
+                }
+            }
+        }
+        if order.len() == self.names.len() {
+            return Ok(order);
+        }
+
+        // Walk backwards through unsorted inputs until a wire repeats
+        let mut path = Vec::new();
+        let mut seen = HashSet::new();
+        let mut wire = (0..self.names.len())
+            .find(|&wire| pending_inputs[wire] > 0)
+            .unwrap();
+        while seen.insert(wire) {
+            path.push(wire);
+            wire = self
+                .inputs(wire)
+                .find(|&input| pending_inputs[input] > 0)
+                .unwrap();
+        }
+        let start = path.iter().position(|&w| w == wire).unwrap();
+        let mut cycle = path[start..]
+            .iter()
+            .rev()
+            .map(|&wire| self.names[wire].to_owned())
+            .collect::<Vec<_>>();
+        cycle.push(cycle[0].to_owned());
+        Err(CircuitError::Cycle(cycle))
+    }
+}
+
+impl<W: Word> fmt::Display for Circuit<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in self.instructions() {
+            writeln!(f, "{instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn circuit(lines: &[&str]) -> Result<Circuit, CircuitError> {
+        let instructions = lines
+            .iter()
+            .map(|line| Instruction::from_str(line).unwrap())
+            .collect::<Vec<_>>();
+        Circuit::new(&instructions)
+    }
+
+    #[test]
+    fn example() -> anyhow::Result<()> {
+        let values = circuit(&[
+            "x AND y -> d",
+            "x OR y -> e",
+            "x LSHIFT 2 -> f",
+            "y RSHIFT 2 -> g",
+            "NOT x -> h",
+            "NOT y -> i",
+            "123 -> x",
+            "456 -> y",
+        ])?
+        .evaluate()?;
+        let expected = [
+            ("d", 72),
+            ("e", 507),
+            ("f", 492),
+            ("g", 114),
+            ("h", 65412),
+            ("i", 65079),
+            ("x", 123),
+            ("y", 456),
+        ];
+        assert_eq!(expected.len(), values.len());
+        for (wire, value) in expected {
+            assert_eq!(Some(&value), values.get(wire), "{wire}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(CircuitError::MultiplyDriven("a".to_owned())),
+            circuit(&["1 -> a", "2 -> a"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(CircuitError::Cycle(vec![
+                "b".to_owned(),
+                "c".to_owned(),
+                "d".to_owned(),
+                "b".to_owned()
+            ])),
+            circuit(&["1 -> a", "a AND d -> b", "b -> c", "NOT c -> d"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(CircuitError::Undriven("y".to_owned())),
+            circuit(&["1 -> x", "x OR y -> a"]).and_then(|c| c.evaluate())
+        );
+        assert_eq!(
+            Err(CircuitError::UnknownWire("z".to_owned())),
+            circuit(&["1 -> x"]).and_then(|c| c.probe("z", &[]))
+        );
+        assert_eq!(
+            Err(CircuitError::Undriven("z".to_owned())),
+            circuit(&["1 -> x", "z -> y"]).and_then(|c| c.evaluate_cone(&["x", "z"], &[]))
+        );
+    }
+
+    #[test]
+    fn cone() -> anyhow::Result<()> {
+        let circuit = circuit(&["1 -> x", "x LSHIFT 2 -> a", "y OR 1 -> b", "NOT b -> c"])?;
+        assert_eq!(
+            Err(CircuitError::Undriven("y".to_owned())),
+            circuit.evaluate()
+        );
+        let values = circuit.evaluate_cone(&["a"], &[])?;
+        assert_eq!(
+            HashMap::from([("x".to_owned(), 1), ("a".to_owned(), 4)]),
+            values
+        );
+        let values = circuit.evaluate_cone(&["c"], &[("y", 4)])?;
+        assert_eq!(Some(&65530), values.get("c"));
+        assert_eq!(3, values.len());
+        Ok(())
+    }
+
+    #[test]
+    fn overrides() -> anyhow::Result<()> {
+        let circuit = circuit(&[
+            "x AND y -> d",
+            "d LSHIFT 1 -> a",
+            "NOT x -> h",
+            "123 -> x",
+            "z OR 1 -> y",
+        ])?;
+        assert!(circuit.evaluate().is_err());
+        assert_eq!(2, circuit.probe("a", &[("z", 0)])?);
+        assert_eq!(246, circuit.probe("a", &[("z", 0xfe)])?);
+        assert_eq!(6, circuit.probe("a", &[("z", 2), ("x", 3)])?);
+        assert_eq!(4, circuit.probe("a", &[("d", 2)])?);
+
+        let values = circuit.evaluate_with(&[("z", 0), ("d", 7)])?;
+        assert_eq!(Some(&14), values.get("a"));
+        assert_eq!(Some(&7), values.get("d"));
+        assert_eq!(Some(&65412), values.get("h"));
+        assert_eq!(6, values.len());
+        Ok(())
+    }
+
+    #[test]
+    fn widths() -> anyhow::Result<()> {
+        let instructions = [
+            "200 -> x",
+            "100 -> y",
+            "x ADD y -> a",
+            "y SUB x -> b",
+            "x XOR y -> c",
+            "x NAND y -> d",
+            "x NOR y -> e",
+            "x LROTATE 1 -> f",
+            "x RROTATE 12 -> g",
+            "x LSHIFT 8 -> h",
+        ]
+        .iter()
+        .map(|line| Instruction::<u8>::from_str(line))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+        let values = Circuit::new(&instructions)?.evaluate()?;
+        for (wire, value) in [
+            ("a", 44),
+            ("b", 156),
+            ("c", 172),
+            ("d", 191),
+            ("e", 19),
+            ("f", 145),
+            ("g", 140),
+            ("h", 0),
+        ] {
+            assert_eq!(Some(&value), values.get(wire), "{wire}");
+        }
+
+        let instructions = ["1 LSHIFT 40 -> a", "NOT 0 -> m", "m RSHIFT 63 -> b"]
+            .iter()
+            .map(|line| Instruction::<u64>::from_str(line))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let circuit = Circuit::new(&instructions)?;
+        assert_eq!(1 << 40, circuit.probe("a", &[])?);
+        assert_eq!(1, circuit.probe("b", &[])?);
+        Ok(())
+    }
+}
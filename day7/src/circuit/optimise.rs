@@ -0,0 +1,264 @@
+use std::fmt;
+
+use super::{Circuit, CircuitError, Gate, Operand, Word};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OptimiseReport {
+    pub gates_before: usize,
+    pub gates_after: usize,
+    // Gates which reduced to a constant
+    pub folded: usize,
+    // Gates which reduced to passing through one of their inputs
+    pub simplified: usize,
+    // Gates which do not contribute to any output
+    pub removed: usize,
+}
+
+impl fmt::Display for OptimiseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} gates reduced to {}: {} folded to constants, {} simplified, {} removed",
+            self.gates_before, self.gates_after, self.folded, self.simplified, self.removed
+        )
+    }
+}
+
+enum Change {
+    None,
+    Folded,
+    Simplified,
+}
+
+impl<W: Word> Circuit<W> {
+    // An equivalent circuit for the given output wires, with constants folded, identity
+    // gates bypassed and anything not feeding an output removed. Wires listed as inputs
+    // lose their drivers, so that they can still be overridden in the new circuit. The
+    // outputs and inputs are kept even if nothing drives them.
+    pub fn optimise(
+        &self,
+        outputs: &[&str],
+        inputs: &[&str],
+    ) -> Result<(Circuit<W>, OptimiseReport), CircuitError> {
+        let kept = outputs.iter().chain(inputs).copied().collect::<Vec<_>>();
+        let outputs = outputs
+            .iter()
+            .map(|wire| self.index(wire))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut drivers = self.drivers.clone();
+        for wire in inputs {
+            drivers[self.index(wire)?] = None;
+        }
+
+        let mut report = OptimiseReport {
+            gates_before: self.drivers.iter().flatten().count(),
+            ..OptimiseReport::default()
+        };
+        let mut constants = vec![None; self.names.len()];
+        let mut aliases = (0..self.names.len()).collect::<Vec<_>>();
+        for &wire in &self.order {
+            let Some(gate) = drivers[wire] else {
+                continue;
+            };
+            let gate = gate.map_inputs(|operand| match operand {
+                Operand::Wire(input) => match constants[input] {
+                    Some(v) => Operand::Value(v),
+                    None => Operand::Wire(aliases[input]),
+                },
+                value => value,
+            });
+            let (gate, change) = Self::simplify(gate);
+            match change {
+                Change::Folded => report.folded += 1,
+                Change::Simplified => report.simplified += 1,
+                Change::None => {}
+            }
+            match gate {
+                Gate::Set(Operand::Value(v)) => constants[wire] = Some(v),
+                Gate::Set(Operand::Wire(input)) => aliases[wire] = input,
+                _ => {}
+            }
+            drivers[wire] = Some(gate);
+        }
+
+        let mut needed = vec![false; self.names.len()];
+        let mut pending = outputs;
+        while let Some(wire) = pending.pop() {
+            if needed[wire] {
+                continue;
+            }
+            needed[wire] = true;
+            for operand in drivers[wire].iter().flat_map(|gate| gate.inputs()) {
+                if let Operand::Wire(input) = operand {
+                    pending.push(input);
+                }
+            }
+        }
+        report.removed = (0..self.names.len())
+            .filter(|&wire| !needed[wire] && drivers[wire].is_some())
+            .count();
+
+        let instructions = self
+            .order
+            .iter()
+            .filter(|&&wire| needed[wire])
+            .filter_map(|&wire| {
+                drivers[wire]
+                    .as_ref()
+                    .map(|gate| self.instruction(wire, gate))
+            })
+            .collect::<Vec<_>>();
+        let circuit = Circuit::with_wires(&instructions, &kept)?;
+        report.gates_after = instructions.len();
+        Ok((circuit, report))
+    }
+
+    fn simplify(gate: Gate<W>) -> (Gate<W>, Change) {
+        use Operand::Value;
+
+        if let Gate::Set(_) = gate {
+            return (gate, Change::None);
+        }
+        let constant = gate.eval(|operand| match operand {
+            Value(v) => Ok(v),
+            Operand::Wire(_) => Err(()),
+        });
+        if let Ok(v) = constant {
+            return (Gate::Set(Value(v)), Change::Folded);
+        }
+        let zero = Value(W::ZERO);
+        let ones = Value(W::MAX);
+        // Shifting by at least the word width clears every bit
+        let too_far = |n: Operand<W>| matches!(n, Value(n) if W::MAX.shift_left(n) == W::ZERO);
+        let simplified = match gate {
+            Gate::And(s, o) | Gate::And(o, s) if o == ones => Gate::Set(s),
+            Gate::Or(s, o) | Gate::Or(o, s) if o == zero => Gate::Set(s),
+            Gate::Xor(s, o) | Gate::Xor(o, s) if o == zero => Gate::Set(s),
+            Gate::Add(s, o) | Gate::Add(o, s) if o == zero => Gate::Set(s),
+            Gate::And(s1, s2) | Gate::Or(s1, s2) if s1 == s2 => Gate::Set(s1),
+            Gate::Sub(s, o)
+            | Gate::LShift(s, o)
+            | Gate::RShift(s, o)
+            | Gate::LRotate(s, o)
+            | Gate::RRotate(s, o)
+                if o == zero =>
+            {
+                Gate::Set(s)
+            }
+            Gate::And(_, o) | Gate::And(o, _) if o == zero => Gate::Set(zero),
+            Gate::Or(_, o) | Gate::Or(o, _) if o == ones => Gate::Set(ones),
+            Gate::Xor(s1, s2) | Gate::Sub(s1, s2) if s1 == s2 => Gate::Set(zero),
+            Gate::LShift(_, n) | Gate::RShift(_, n) if too_far(n) => Gate::Set(zero),
+            Gate::LShift(o, _) | Gate::RShift(o, _) | Gate::LRotate(o, _) | Gate::RRotate(o, _)
+                if o == zero =>
+            {
+                Gate::Set(zero)
+            }
+            Gate::LRotate(o, _) | Gate::RRotate(o, _) if o == ones => Gate::Set(ones),
+            _ => return (gate, Change::None),
+        };
+        match simplified {
+            Gate::Set(Value(_)) => (simplified, Change::Folded),
+            _ => (simplified, Change::Simplified),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instruction;
+    use std::str::FromStr;
+
+    fn circuit(lines: &[&str]) -> Circuit {
+        let instructions = lines
+            .iter()
+            .map(|line| Instruction::from_str(line).unwrap())
+            .collect::<Vec<_>>();
+        Circuit::new(&instructions).unwrap()
+    }
+
+    #[test]
+    fn folding() -> anyhow::Result<()> {
+        let original = circuit(&[
+            "123 -> x",
+            "456 -> y",
+            "x AND y -> d",
+            "d OR z -> a",
+            "NOT x -> h",
+        ]);
+        let (optimised, report) = original.optimise(&["a"], &[])?;
+        assert_eq!("72 OR z -> a\n", optimised.to_string());
+        assert_eq!(2, report.folded);
+        assert_eq!(4, report.removed);
+        assert_eq!((5, 1), (report.gates_before, report.gates_after));
+        assert_eq!(
+            original.probe("a", &[("z", 5)])?,
+            optimised.probe("a", &[("z", 5)])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn identities() -> anyhow::Result<()> {
+        let original = circuit(&[
+            "p AND 65535 -> q",
+            "q OR 0 -> r",
+            "r LSHIFT 0 -> s",
+            "s OR k -> a",
+            "k RSHIFT 16 -> t",
+            "t OR a -> b",
+        ]);
+        let (optimised, report) = original.optimise(&["a", "b"], &[])?;
+        assert_eq!("p OR k -> a\na -> b\n", optimised.to_string());
+        assert_eq!(4, report.simplified);
+        assert_eq!(1, report.folded);
+        assert_eq!(4, report.removed);
+        for (p, k) in [(0, 0), (1234, 4321), (65535, 7)] {
+            let overrides = [("p", p), ("k", k)];
+            assert_eq!(
+                original.evaluate_with(&overrides)?["b"],
+                optimised.evaluate_with(&overrides)?["b"]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn inputs() -> anyhow::Result<()> {
+        let original = circuit(&["14146 -> b", "b AND 7 -> a"]);
+        assert_eq!("2 -> a\n", original.optimise(&["a"], &[])?.0.to_string());
+        let (optimised, _) = original.optimise(&["a"], &["b"])?;
+        assert_eq!("b AND 7 -> a\n", optimised.to_string());
+        assert_eq!(5, optimised.probe("a", &[("b", 13)])?);
+
+        // Nothing drives z or feeds from c, but both keep the errors they had
+        let original = circuit(&["1 -> x", "x AND z -> y", "2 -> c"]);
+        let (optimised, _) = original.optimise(&["z"], &["c"])?;
+        for wire in ["z", "c"] {
+            assert_eq!(
+                Err(CircuitError::Undriven(wire.to_owned())),
+                optimised.probe(wire, &[])
+            );
+        }
+        assert_eq!(3, optimised.probe("c", &[("c", 3)])?);
+        Ok(())
+    }
+
+    #[test]
+    fn extended_identities() -> anyhow::Result<()> {
+        let original = circuit(&[
+            "p XOR 0 -> q",
+            "0 ADD q -> r",
+            "r LROTATE 0 -> s",
+            "s SUB s -> t",
+            "k XOR k -> u",
+            "t OR u -> a",
+            "k RROTATE 3 -> b",
+        ]);
+        let (optimised, report) = original.optimise(&["a", "b"], &[])?;
+        assert_eq!("0 -> a\nk RROTATE 3 -> b\n", optimised.to_string());
+        assert_eq!((3, 3), (report.simplified, report.folded));
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, BitXor, Not},
+    str::FromStr,
+};
+
+// An unsigned word carried by every wire. Shifting by the word width or more gives zero,
+// rotation is modulo the word width, and addition and subtraction wrap.
+pub trait Word:
+    Copy
+    + Default
+    + Eq
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Not<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+{
+    const BITS: u32;
+    const ZERO: Self;
+    const MAX: Self;
+
+    fn shift_left(self, n: Self) -> Self;
+    fn shift_right(self, n: Self) -> Self;
+    fn rotate_left(self, n: Self) -> Self;
+    fn rotate_right(self, n: Self) -> Self;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+}
+
+macro_rules! word {
+    ($($t:ty),*) => {$(
+        impl Word for $t {
+            const BITS: u32 = <$t>::BITS;
+            const ZERO: Self = 0;
+            const MAX: Self = <$t>::MAX;
+
+            fn shift_left(self, n: Self) -> Self {
+                if n >= Self::BITS as Self { 0 } else { self << n }
+            }
+
+            fn shift_right(self, n: Self) -> Self {
+                if n >= Self::BITS as Self { 0 } else { self >> n }
+            }
+
+            fn rotate_left(self, n: Self) -> Self {
+                <$t>::rotate_left(self, (n % Self::BITS as Self) as u32)
+            }
+
+            fn rotate_right(self, n: Self) -> Self {
+                <$t>::rotate_right(self, (n % Self::BITS as Self) as u32)
+            }
+
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+
+            fn wrapping_sub(self, other: Self) -> Self {
+                <$t>::wrapping_sub(self, other)
+            }
+        }
+    )*};
+}
+
+word!(u8, u16, u32, u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(0, Word::shift_left(0x80u8, 1));
+        assert_eq!(0x100, Word::shift_left(0x80u16, 1));
+        assert_eq!(0, Word::shift_right(u64::MAX, 64));
+        assert_eq!(0x01, Word::rotate_left(0x80u8, 9));
+        assert_eq!(0x8000_0000, Word::rotate_right(1u32, 1));
+        assert_eq!(44, Word::wrapping_add(200u8, 100));
+        assert_eq!(u16::MAX, Word::wrapping_sub(0u16, 1));
+    }
+}
@@ -1,18 +1,23 @@
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
+use circuit::{Circuit, CircuitError, OptimiseReport, Word};
 use std::{
-    collections::HashMap,
+    fmt,
     fs::File,
     io::{BufRead, BufReader},
     str::FromStr,
 };
 
+pub mod circuit;
+
 pub fn load(filename: &str) -> Result<Solution> {
     let file = File::open(filename)?;
 
     let reader = BufReader::new(file);
     let mut solution = Solution::new();
-    for s in reader.lines().flatten() {
-        solution.add_instruction(Instruction::from_str(&s).unwrap());
+    for (n, s) in reader.lines().flatten().enumerate() {
+        let instruction =
+            Instruction::from_str(&s).with_context(|| format!("{filename}: line {}", n + 1))?;
+        solution.add_instruction(instruction);
     }
     Ok(solution)
 }
@@ -52,6 +57,23 @@ impl Solution {
     pub fn answer_part2(&self) -> Option<i64> {
         self.answer_part2
     }
+
+    pub fn circuit(&self) -> Result<Circuit, CircuitError> {
+        Circuit::new(&self.instructions)
+    }
+
+    // The circuit reduced to just what is needed for wire a.
+    pub fn optimised(&self) -> Result<(Circuit, OptimiseReport), CircuitError> {
+        self.circuit()?.optimise(&["a"], &[])
+    }
+
+    // The circuit as a Graphviz graph, annotated with the values feeding wire a if it can
+    // be evaluated.
+    pub fn to_dot(&self) -> Result<String, CircuitError> {
+        let circuit = self.circuit()?;
+        let values = circuit.evaluate_cone(&["a"], &[]).ok();
+        Ok(circuit.to_dot(values.as_ref(), Some("a")))
+    }
 }
 
 impl Solution {
@@ -60,159 +82,152 @@ impl Solution {
     }
 
     fn analyse_part1(&self) -> Option<i64> {
-        let mut wires = HashMap::new();
-        loop {
-            for i in &self.instructions {
-                log::trace!("p2 {i:?}");
-                i.update_wire(&mut wires);
-            }
-            log::trace!("p1 {wires:#?}");
-            log::debug!("p1 a: {:?}", wires.get("a").unwrap_or(&None));
-            if wires.get("a").unwrap_or(&None).to_owned().is_some() {
-                break;
-            }
-        }
-        wires.get("a").unwrap_or(&None).to_owned().map(|v| v as i64)
+        self.signal("a", &[])
     }
 
     fn analyse_part2(&self) -> Option<i64> {
-        let mut wires = HashMap::new();
-        wires.insert("b".to_owned(), self.answer_part1.map(|v| v as u16));
-        loop {
-            for i in &self.instructions {
-                if i.get_target() == "b" {
-                    continue;
-                }
-                log::trace!("p2 {i:?}");
-                i.update_wire(&mut wires);
-            }
-            log::trace!("p2 {wires:#?}");
-            log::debug!("p2 a: {:?}", wires.get("a").unwrap_or(&None));
-            if wires.get("a").unwrap_or(&None).to_owned().is_some() {
-                break;
+        let b = self.answer_part1? as u16;
+        self.signal("a", &[("b", b)])
+    }
+
+    fn signal(&self, wire: &str, overrides: &[(&str, u16)]) -> Option<i64> {
+        match self.circuit().and_then(|c| c.probe(wire, overrides)) {
+            Ok(v) => Some(v as i64),
+            Err(e) => {
+                log::error!("{wire}: {e}");
+                None
             }
         }
-        wires.get("a").unwrap_or(&None).to_owned().map(|v| v as i64)
     }
 }
 
 #[derive(Debug)]
-enum Instruction {
-    Set(String, Signal),
-    Not(String, Signal),
-    And(String, Signal, Signal),
-    Or(String, Signal, Signal),
-    LShift(String, Signal, Signal),
-    RShift(String, Signal, Signal),
+pub enum Instruction<W: Word = u16> {
+    Set(String, Signal<W>),
+    Not(String, Signal<W>),
+    And(String, Signal<W>, Signal<W>),
+    Or(String, Signal<W>, Signal<W>),
+    Xor(String, Signal<W>, Signal<W>),
+    Nand(String, Signal<W>, Signal<W>),
+    Nor(String, Signal<W>, Signal<W>),
+    LShift(String, Signal<W>, Signal<W>),
+    RShift(String, Signal<W>, Signal<W>),
+    LRotate(String, Signal<W>, Signal<W>),
+    RRotate(String, Signal<W>, Signal<W>),
+    Add(String, Signal<W>, Signal<W>),
+    Sub(String, Signal<W>, Signal<W>),
 }
 
-impl Instruction {
+impl<W: Word> Instruction<W> {
     fn get_target(&self) -> String {
         match self {
             Self::Set(t, _) => t.to_owned(),
             Self::Not(t, _) => t.to_owned(),
             Self::And(t, _, _) => t.to_owned(),
             Self::Or(t, _, _) => t.to_owned(),
+            Self::Xor(t, _, _) => t.to_owned(),
+            Self::Nand(t, _, _) => t.to_owned(),
+            Self::Nor(t, _, _) => t.to_owned(),
             Self::LShift(t, _, _) => t.to_owned(),
             Self::RShift(t, _, _) => t.to_owned(),
+            Self::LRotate(t, _, _) => t.to_owned(),
+            Self::RRotate(t, _, _) => t.to_owned(),
+            Self::Add(t, _, _) => t.to_owned(),
+            Self::Sub(t, _, _) => t.to_owned(),
         }
     }
-
-    fn update_wire(&self, wires: &mut HashMap<String, Option<u16>>) {
-        match self {
-            Self::Set(t, s) => {
-                wires.insert(t.to_owned(), s.value(wires));
-            }
-            Self::Not(t, s) => {
-                wires.insert(t.to_owned(), s.value(wires).map(|v| !v));
-            }
-            Self::And(t, s1, s2) => {
-                wires.insert(
-                    t.to_owned(),
-                    s1.value(wires)
-                        .and_then(|v1| s2.value(wires).map(|v2| v1 & v2)),
-                );
-            }
-            Self::Or(t, s1, s2) => {
-                wires.insert(
-                    t.to_owned(),
-                    s1.value(wires)
-                        .and_then(|v1| s2.value(wires).map(|v2| v1 | v2)),
-                );
-            }
-            Self::LShift(t, s1, s2) => {
-                wires.insert(
-                    t.to_owned(),
-                    s1.value(wires)
-                        .and_then(|v1| s2.value(wires).map(|v2| v1 << v2)),
-                );
-            }
-            Self::RShift(t, s1, s2) => {
-                wires.insert(
-                    t.to_owned(),
-                    s1.value(wires)
-                        .and_then(|v1| s2.value(wires).map(|v2| v1 >> v2)),
-                );
-            }
-        };
-    }
 }
 
 #[derive(Debug)]
-enum Signal {
-    Value(u16),
+pub enum Signal<W: Word = u16> {
+    Value(W),
     Wire(String),
 }
 
-impl Signal {
-    fn value(&self, wires: &HashMap<String, Option<u16>>) -> Option<u16> {
+impl<W: Word> fmt::Display for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Set(t, s) => write!(f, "{s} -> {t}"),
+            Self::Not(t, s) => write!(f, "NOT {s} -> {t}"),
+            Self::And(t, s1, s2) => write!(f, "{s1} AND {s2} -> {t}"),
+            Self::Or(t, s1, s2) => write!(f, "{s1} OR {s2} -> {t}"),
+            Self::Xor(t, s1, s2) => write!(f, "{s1} XOR {s2} -> {t}"),
+            Self::Nand(t, s1, s2) => write!(f, "{s1} NAND {s2} -> {t}"),
+            Self::Nor(t, s1, s2) => write!(f, "{s1} NOR {s2} -> {t}"),
+            Self::LShift(t, s1, s2) => write!(f, "{s1} LSHIFT {s2} -> {t}"),
+            Self::RShift(t, s1, s2) => write!(f, "{s1} RSHIFT {s2} -> {t}"),
+            Self::LRotate(t, s1, s2) => write!(f, "{s1} LROTATE {s2} -> {t}"),
+            Self::RRotate(t, s1, s2) => write!(f, "{s1} RROTATE {s2} -> {t}"),
+            Self::Add(t, s1, s2) => write!(f, "{s1} ADD {s2} -> {t}"),
+            Self::Sub(t, s1, s2) => write!(f, "{s1} SUB {s2} -> {t}"),
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Signal<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Value(v) => Some(*v),
-            Self::Wire(s) => wires.get(s).unwrap_or(&None).to_owned(),
+            Self::Value(v) => write!(f, "{v}"),
+            Self::Wire(w) => write!(f, "{w}"),
         }
     }
 }
 
-impl FromStr for Instruction {
+impl<W: Word> FromStr for Instruction<W> {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let r = regex::Regex::new(r"^(((?P<source1>[0-9a-z]+) )?(?P<op>[A-Z]+) )?(?P<source2>[0-9a-z]+) -> (?P<target>[a-z]+)$").unwrap();
-        if let Some(cap) = r.captures(s) {
-            let source1 = cap
-                .name("source1")
-                .map(|s| s.as_str())
-                .and_then(|s| Signal::from_str(s).ok());
-            let op = cap.name("op").map(|s| s.as_str());
-            let source2 = cap
-                .name("source2")
-                .map(|s| s.as_str())
-                .and_then(|s| Signal::from_str(s).ok());
-            let target = cap.name("target").map(|s| s.as_str()).unwrap().to_owned();
-            println!("matched: {source1:?} {op:?} {source2:?} -> {target:?}");
-            let i = match op {
-                Some("AND") => Self::And(target, source1.unwrap(), source2.unwrap()),
-                Some("OR") => Self::Or(target, source1.unwrap(), source2.unwrap()),
-                Some("RSHIFT") => Self::RShift(target, source1.unwrap(), source2.unwrap()),
-                Some("LSHIFT") => Self::LShift(target, source1.unwrap(), source2.unwrap()),
-                Some("NOT") => Self::Not(target, source2.unwrap()),
-                None => Self::Set(target, source2.unwrap()),
-                _ => unreachable!(),
+        let cap = r
+            .captures(s)
+            .ok_or_else(|| anyhow!("expected [[source] GATE] source -> target, not {s}"))?;
+        let source1 = cap
+            .name("source1")
+            .map(|s| Signal::from_str(s.as_str()))
+            .transpose()?;
+        let op = cap.name("op").map(|s| s.as_str());
+        let source2 = cap
+            .name("source2")
+            .map(|s| Signal::from_str(s.as_str()))
+            .transpose()?;
+        let target = cap.name("target").map(|s| s.as_str()).unwrap().to_owned();
+        let source2 = source2.unwrap();
+        let Some(op) = op else {
+            return Ok(Self::Set(target, source2));
+        };
+        if op == "NOT" {
+            return match source1 {
+                None => Ok(Self::Not(target, source2)),
+                Some(_) => Err(anyhow!("NOT takes one input in {s}")),
             };
-            Ok(i)
-        } else {
-            println!("{s} failed to match");
-            todo!()
         }
+        let source1 = source1.ok_or_else(|| anyhow!("{op} needs two inputs in {s}"))?;
+        let i = match op {
+            "AND" => Self::And(target, source1, source2),
+            "OR" => Self::Or(target, source1, source2),
+            "XOR" => Self::Xor(target, source1, source2),
+            "NAND" => Self::Nand(target, source1, source2),
+            "NOR" => Self::Nor(target, source1, source2),
+            "RSHIFT" => Self::RShift(target, source1, source2),
+            "LSHIFT" => Self::LShift(target, source1, source2),
+            "RROTATE" => Self::RRotate(target, source1, source2),
+            "LROTATE" => Self::LRotate(target, source1, source2),
+            "ADD" => Self::Add(target, source1, source2),
+            "SUB" => Self::Sub(target, source1, source2),
+            op => return Err(anyhow!("unknown gate {op} in {s}")),
+        };
+        Ok(i)
     }
 }
 
-impl FromStr for Signal {
+impl<W: Word> FromStr for Signal<W> {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.chars().all(|c| c.is_numeric()) {
-            Ok(Self::Value(s.parse().unwrap()))
+            s.parse()
+                .map(Self::Value)
+                .map_err(|_| anyhow!("{s} does not fit in {} bits", W::BITS))
         } else {
             Ok(Self::Wire(s.to_owned()))
         }
@@ -236,8 +251,41 @@ mod tests {
             "NOT y -> i",
         ];
         for test in tests {
-            Signal::from_str(test).unwrap();
+            Signal::<u16>::from_str(test).unwrap();
         }
         Ok(())
     }
+
+    #[test]
+    fn extended_syntax() -> Result<()> {
+        for test in [
+            "x XOR y -> a",
+            "x NAND 3 -> b",
+            "x NOR y -> c",
+            "x LROTATE 4 -> d",
+            "x RROTATE y -> e",
+            "200 ADD x -> f",
+            "x SUB 1 -> g",
+        ] {
+            assert_eq!(test, Instruction::<u8>::from_str(test)?.to_string());
+        }
+        assert!(Instruction::<u8>::from_str("300 -> x").is_err());
+        assert!(Instruction::<u16>::from_str("300 -> x").is_ok());
+        assert!(Instruction::<u16>::from_str("x MUL y -> z").is_err());
+        for bad in ["AND y -> z", "x NOT y -> z", "x y -> z", "-> z", "x -> Z"] {
+            assert!(Instruction::<u16>::from_str(bad).is_err(), "{bad}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bad_line() -> Result<()> {
+        let filename = std::env::temp_dir().join("day7-bad-line.input");
+        std::fs::write(&filename, "123 -> x\nAND y -> z\n")?;
+        let loaded = load(filename.to_str().unwrap());
+        std::fs::remove_file(&filename)?;
+        let error = loaded.unwrap_err();
+        assert!(format!("{error:#}").contains("line 2"), "{error:#}");
+        Ok(())
+    }
 }
@@ -1,13 +1,19 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use day7::load;
 use env_logger::Env;
-use log::{error, info};
+use log::{debug, error, info};
 use yansi::Paint;
 
 fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
     let mut solution = load("input/day7.input")?;
+    if let Some("dot") = std::env::args().nth(1).as_deref() {
+        let filename = std::env::args().nth(2).context("needs a file to write")?;
+        std::fs::write(&filename, solution.to_dot()?)?;
+        info!("{}wrote circuit to {filename}", Paint::masked("🎄 "));
+        return Ok(());
+    }
     info!(
         "{}{}: {:?}",
         Paint::masked("🎄 "),
@@ -33,5 +39,13 @@ fn main() -> Result<()> {
         _ => error!("{}No answer to part2", Paint::masked("🎅 ")),
     }
 
+    match solution.optimised() {
+        Ok((circuit, report)) => {
+            info!("{}optimised: {report}", Paint::masked("🎄 "));
+            debug!("optimised circuit:\n{circuit}");
+        }
+        Err(e) => error!("{}cannot optimise circuit: {e}", Paint::masked("🎄 ")),
+    }
+
     Ok(())
 }